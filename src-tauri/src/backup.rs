@@ -0,0 +1,591 @@
+use crate::compress::{CompressError, CompressErrorType};
+use crate::error::Error;
+use crate::history::{hash_file, now};
+use specta::Type;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+
+const JOURNAL_FILE: &str = "journal.jsonl";
+const BACKUP_DIR: &str = "backups";
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+static JOURNAL: Mutex<Option<Journal>> = Mutex::new(None);
+// Files compressed since the list was last cleared, started on the first one
+static BATCH_ID: Mutex<Option<u64>> = Mutex::new(None);
+static TEMP_ID: AtomicU64 = AtomicU64::new(0);
+// Recorders holding backups not in the journal yet, with when they made the first one
+static PENDING: Mutex<Vec<(u64, SystemTime)>> = Mutex::new(Vec::new());
+static RECORDER_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, Copy, PartialEq)]
+pub enum JournalAction {
+    // A new file was written
+    Create,
+    // An existing file was written over
+    Replace,
+    // The original was moved to the OS trash
+    Trash,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+pub struct JournalOp {
+    pub action: JournalAction,
+    pub path: String,
+    // Copy of the file as it was before, only kept when backups are on
+    pub backup: Option<String>,
+}
+
+// Everything process_img changed on disk for one image
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub batch_id: u64,
+    pub path: String,
+    pub ops: Vec<JournalOp>,
+    // Unix seconds
+    pub timestamp: u64,
+    pub undone: bool,
+}
+
+// Appended to while compressing, rewritten when entries are undone or pruned
+struct Journal {
+    dir: PathBuf,
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    fn open(dir: &Path) -> Self {
+        let entries = File::open(dir.join(JOURNAL_FILE))
+            .map(|file| {
+                BufReader::new(file)
+                    .lines()
+                    .map_while(Result::ok)
+                    .filter_map(|line| serde_json::from_str(&line).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Journal {
+            dir: dir.to_path_buf(),
+            entries,
+        }
+    }
+
+    fn record(&mut self, entry: JournalEntry) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(JOURNAL_FILE))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn save(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(&serde_json::to_string(entry)?);
+            text.push('\n');
+        }
+        // Written next to it and renamed, so a crash can't leave half a journal
+        let temp_path = self.dir.join(format!("{}.tmp", JOURNAL_FILE));
+        fs::write(&temp_path, text)?;
+        fs::rename(temp_path, self.dir.join(JOURNAL_FILE))
+    }
+
+    // Newest first, each undone once
+    fn undo(&mut self, matches: impl Fn(&JournalEntry) -> bool) -> Result<u32, Vec<Error>> {
+        let mut errors = vec![];
+        let mut undone = 0;
+        for entry in self.entries.iter_mut().rev() {
+            if entry.undone || !matches(entry) {
+                continue;
+            }
+            match undo_entry(entry) {
+                Ok(()) => {
+                    entry.undone = true;
+                    undone += 1;
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        if let Err(e) = self.save() {
+            errors.push(Error::io(&self.dir.join(JOURNAL_FILE).to_string_lossy(), e));
+        }
+        match errors.is_empty() {
+            true => Ok(undone),
+            false => Err(errors),
+        }
+    }
+
+    // Returns how many backup files were deleted
+    fn prune(&mut self, days: u32) -> io::Result<u32> {
+        let cutoff = now().saturating_sub(days as u64 * DAY_SECS);
+        self.entries.retain(|e| e.timestamp >= cutoff);
+        self.save()?;
+        let kept: HashSet<PathBuf> = self
+            .entries
+            .iter()
+            .flat_map(|e| e.ops.iter())
+            .filter_map(|op| op.backup.as_ref().map(PathBuf::from))
+            .collect();
+        // Files still being compressed have backups the journal doesn't know of yet
+        let oldest_pending = PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(_, started)| *started)
+            .min();
+        let in_use = |path: &Path| match oldest_pending {
+            Some(started) => fs::metadata(path)
+                .and_then(|m| m.modified())
+                .map_or(true, |modified| modified >= started),
+            None => false,
+        };
+        let mut removed = 0;
+        let Ok(dir) = fs::read_dir(self.dir.join(BACKUP_DIR)) else {
+            return Ok(0);
+        };
+        for path in dir.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if !kept.contains(&path) && !in_use(&path) {
+                fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+// Checked up front so a file is never left half restored
+fn undo_entry(entry: &JournalEntry) -> Result<(), Error> {
+    for op in &entry.ops {
+        if op.action == JournalAction::Create {
+            continue;
+        }
+        match &op.backup {
+            Some(backup) if Path::new(backup).exists() => {}
+            Some(backup) => {
+                let missing = io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("The backup {} is missing", backup),
+                );
+                return Err(Error::io(&op.path, missing));
+            }
+            None if op.action == JournalAction::Trash => {
+                return Err(Error::unsupported(
+                    &op.path,
+                    "It has no backup, restore it from the Trash",
+                ))
+            }
+            None => {
+                return Err(Error::unsupported(
+                    &op.path,
+                    "It was replaced without a backup",
+                ))
+            }
+        }
+    }
+    for op in entry.ops.iter().rev() {
+        let result = match &op.backup {
+            Some(backup) => fs::copy(backup, &op.path).map(|_| ()),
+            None => match fs::remove_file(&op.path) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+        };
+        result.map_err(|e| Error::io(&op.path, e))?;
+    }
+    Ok(())
+}
+
+// Files are named by content, so the same original is only stored once.
+// Runs outside the journal lock, so one large copy doesn't hold up every other file.
+fn backup_file(dir: &Path, path: &str) -> io::Result<String> {
+    let dir = dir.join(BACKUP_DIR);
+    fs::create_dir_all(&dir)?;
+    let mut name = hash_file(path)?;
+    if let Some(ext) = Path::new(path).extension() {
+        name = format!("{}.{}", name, ext.to_string_lossy());
+    }
+    let backup_path = dir.join(&name);
+    if backup_path.exists() {
+        // Counts as new, so a prune running meanwhile leaves it alone
+        File::options()
+            .write(true)
+            .open(&backup_path)?
+            .set_modified(SystemTime::now())?;
+    } else {
+        // Unique per copy, two threads may back up the same content at once
+        let id = TEMP_ID.fetch_add(1, Ordering::Relaxed);
+        let temp_path = dir.join(format!(".{}.{}.tmp", name, id));
+        if let Err(e) =
+            fs::copy(path, &temp_path).and_then(|_| fs::rename(&temp_path, &backup_path))
+        {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+    }
+    Ok(backup_path.to_string_lossy().to_string())
+}
+
+fn with_journal<T>(dir: &Path, f: impl FnOnce(&mut Journal) -> T) -> T {
+    let mut journal = JOURNAL.lock().unwrap_or_else(|e| e.into_inner());
+    if journal.as_ref().is_none_or(|j| j.dir != dir) {
+        *journal = Some(Journal::open(dir));
+    }
+    f(journal.as_mut().unwrap())
+}
+
+// What compress_file needs to back up and record its changes
+pub struct Recorder {
+    dir: Option<PathBuf>,
+    backup_originals: bool,
+    ops: Vec<JournalOp>,
+    id: u64,
+}
+
+impl Recorder {
+    pub fn new(app: &tauri::AppHandle, backup_originals: bool) -> Self {
+        Recorder {
+            dir: app.path().app_data_dir().ok(),
+            backup_originals,
+            ops: vec![],
+            id: RECORDER_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn in_dir(dir: &Path, backup_originals: bool) -> Self {
+        Recorder {
+            dir: Some(dir.to_path_buf()),
+            backup_originals,
+            ops: vec![],
+            id: RECORDER_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    // Call before touching `path`. Fails if the backup can't be made, so nothing is lost.
+    pub fn before(&mut self, action: JournalAction, path: &str) -> io::Result<()> {
+        let backup = match &self.dir {
+            Some(dir) if self.backup_originals && action != JournalAction::Create => {
+                let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+                if !pending.iter().any(|(id, _)| *id == self.id) {
+                    pending.push((self.id, SystemTime::now()));
+                }
+                drop(pending);
+                Some(backup_file(dir, path)?)
+            }
+            _ => None,
+        };
+        self.ops.push(JournalOp {
+            action,
+            path: path.to_string(),
+            backup,
+        });
+        Ok(())
+    }
+
+    // Nothing is recorded if nothing was touched
+    pub fn finish(&mut self, path: &str) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        if self.ops.is_empty() {
+            return;
+        }
+        let ops = std::mem::take(&mut self.ops);
+        let result = with_journal(dir, |j| {
+            let entry = JournalEntry {
+                batch_id: batch_id(j),
+                path: path.to_string(),
+                ops,
+                timestamp: now(),
+                undone: false,
+            };
+            j.record(entry)
+        });
+        if let Err(e) = result {
+            println!("Cannot write journal: {}", e);
+        }
+        self.unregister();
+    }
+
+    fn unregister(&self) {
+        PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(id, _)| *id != self.id);
+    }
+}
+
+// A file that failed part way may have made backups without recording them
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+// Microseconds, which stay exact as JS numbers. Always past every id in the journal,
+// so batches never share one, even across restarts or within the same second.
+fn batch_id(journal: &Journal) -> u64 {
+    *BATCH_ID
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| next_batch_id(journal))
+}
+
+fn next_batch_id(journal: &Journal) -> u64 {
+    let last = journal
+        .entries
+        .iter()
+        .map(|e| e.batch_id)
+        .max()
+        .unwrap_or(0);
+    let micros = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as u64);
+    micros.max(last + 1)
+}
+
+fn journal_dir(app: &tauri::AppHandle) -> Result<PathBuf, CompressError> {
+    app.path()
+        .app_data_dir()
+        .map_err(|_| CompressError::new(CompressErrorType::Io, "Cannot find the app data folder"))
+}
+
+// Every entry that couldn't be undone is listed, the type is the first one's
+fn undo_error(errors: Vec<Error>) -> CompressError {
+    let mut errors = errors.into_iter().map(CompressError::from);
+    let mut first = errors.next().expect("Undo errors can't be empty");
+    for err in errors {
+        first.error = format!("{}\n{}", first.error, err.error);
+        first.path = None;
+    }
+    first
+}
+
+// Newest first
+#[tauri::command]
+#[specta::specta]
+pub async fn get_journal(app: tauri::AppHandle) -> Result<Vec<JournalEntry>, CompressError> {
+    let dir = journal_dir(&app)?;
+    Ok(with_journal(&dir, |j| {
+        j.entries.iter().rev().cloned().collect()
+    }))
+}
+
+// The next compressed file starts a new batch
+#[tauri::command]
+#[specta::specta]
+pub async fn new_batch() {
+    *BATCH_ID.lock().unwrap() = None;
+}
+
+// Undoes the last change to `path`, returns how many entries were undone
+#[tauri::command]
+#[specta::specta]
+pub async fn undo_file(app: tauri::AppHandle, path: String) -> Result<u32, CompressError> {
+    let dir = journal_dir(&app)?;
+    with_journal(&dir, |j| {
+        let last = j
+            .entries
+            .iter()
+            .rposition(|e| !e.undone && e.path == path)
+            .ok_or_else(|| {
+                CompressError::new(CompressErrorType::Invalid, "Nothing to undo for this file")
+            })?;
+        let timestamp = j.entries[last].timestamp;
+        j.undo(|e| e.path == path && e.timestamp == timestamp)
+            .map_err(undo_error)
+    })
+}
+
+// Undoes every file of a batch, the current one if none is given
+#[tauri::command]
+#[specta::specta]
+pub async fn undo_batch(
+    app: tauri::AppHandle,
+    batch_id: Option<u64>,
+) -> Result<u32, CompressError> {
+    let dir = journal_dir(&app)?;
+    let batch_id = match batch_id {
+        Some(batch_id) => batch_id,
+        None => (*BATCH_ID.lock().unwrap())
+            .ok_or_else(|| CompressError::new(CompressErrorType::Invalid, "Nothing to undo"))?,
+    };
+    with_journal(&dir, |j| j.undo(|e| e.batch_id == batch_id)).map_err(undo_error)
+}
+
+// Forgets entries older than `days` and deletes backups nothing refers to anymore
+#[tauri::command]
+#[specta::specta]
+pub async fn prune_backups(app: tauri::AppHandle, days: u32) -> Result<u32, CompressError> {
+    let dir = journal_dir(&app)?;
+    with_journal(&dir, |j| j.prune(days)).map_err(|e| Error::io(&dir.to_string_lossy(), e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_undo() {
+        let dir = test_dir("alic-test-journal");
+        let mut journal = Journal::open(&dir);
+        let original = dir.join("a.png").to_string_lossy().to_string();
+        let out = dir.join("a.min.png").to_string_lossy().to_string();
+
+        // Overwritten in place with a backup, then written next to it without one
+        fs::write(&original, "original").unwrap();
+        let backup = backup_file(&dir, &original).unwrap();
+        fs::write(&original, "compressed").unwrap();
+        fs::write(&out, "compressed").unwrap();
+        let overwrite = JournalEntry {
+            batch_id: 1,
+            path: original.clone(),
+            ops: vec![
+                JournalOp {
+                    action: JournalAction::Trash,
+                    path: original.clone(),
+                    backup: Some(backup),
+                },
+                JournalOp {
+                    action: JournalAction::Create,
+                    path: original.clone(),
+                    backup: None,
+                },
+            ],
+            timestamp: now(),
+            undone: false,
+        };
+        let create = JournalEntry {
+            batch_id: 2,
+            path: original.clone(),
+            ops: vec![JournalOp {
+                action: JournalAction::Create,
+                path: out.clone(),
+                backup: None,
+            }],
+            timestamp: now(),
+            undone: false,
+        };
+        journal.record(overwrite).unwrap();
+        journal.record(create).unwrap();
+
+        assert_eq!(journal.undo(|e| e.batch_id == 2).unwrap(), 1);
+        assert!(!Path::new(&out).exists());
+        assert_eq!(journal.undo(|e| e.batch_id == 1).unwrap(), 1);
+        assert_eq!(fs::read_to_string(&original).unwrap(), "original");
+        // Already undone
+        assert_eq!(journal.undo(|_| true).unwrap(), 0);
+        assert!(Journal::open(&dir).entries.iter().all(|e| e.undone));
+    }
+
+    #[test]
+    fn test_undo_without_backup() {
+        let dir = test_dir("alic-test-journal-trash");
+        let mut journal = Journal::open(&dir);
+        let path = dir.join("a.png").to_string_lossy().to_string();
+        fs::write(&path, "compressed").unwrap();
+        journal.entries.push(JournalEntry {
+            batch_id: 1,
+            path: path.clone(),
+            ops: vec![
+                JournalOp {
+                    action: JournalAction::Trash,
+                    path: path.clone(),
+                    backup: None,
+                },
+                JournalOp {
+                    action: JournalAction::Create,
+                    path: path.clone(),
+                    backup: None,
+                },
+            ],
+            timestamp: now(),
+            undone: false,
+        });
+        assert!(journal.undo(|_| true).is_err());
+        // The output is left alone when the original can't come back
+        assert!(Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = test_dir("alic-test-journal-prune");
+        let mut journal = Journal::open(&dir);
+        let path = dir.join("a.png").to_string_lossy().to_string();
+        for (content, timestamp) in [("old", 0), ("new", now())] {
+            fs::write(&path, content).unwrap();
+            let backup = backup_file(&dir, &path).unwrap();
+            if timestamp == 0 {
+                File::options()
+                    .write(true)
+                    .open(&backup)
+                    .unwrap()
+                    .set_modified(UNIX_EPOCH)
+                    .unwrap();
+            }
+            journal.entries.push(JournalEntry {
+                batch_id: timestamp,
+                path: path.clone(),
+                ops: vec![JournalOp {
+                    action: JournalAction::Replace,
+                    path: path.clone(),
+                    backup: Some(backup),
+                }],
+                timestamp,
+                undone: false,
+            });
+        }
+        assert_eq!(journal.prune(30).unwrap(), 1);
+        assert_eq!(journal.entries.len(), 1);
+        let backup = journal.entries[0].ops[0].backup.clone().unwrap();
+        assert_eq!(fs::read_to_string(backup).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_prune_keeps_pending() {
+        let dir = test_dir("alic-test-journal-pending");
+        let path = dir.join("a.png").to_string_lossy().to_string();
+        fs::write(&path, "original").unwrap();
+        let mut recorder = Recorder::in_dir(&dir, true);
+        recorder.before(JournalAction::Replace, &path).unwrap();
+        // The backup isn't in the journal until the recorder finishes
+        assert_eq!(Journal::open(&dir).prune(30).unwrap(), 0);
+        recorder.finish(&path);
+        let journal = Journal::open(&dir);
+        assert_eq!(journal.entries.len(), 1);
+        let backup = journal.entries[0].ops[0].backup.clone().unwrap();
+        assert_eq!(fs::read_to_string(backup).unwrap(), "original");
+    }
+
+    #[test]
+    fn test_batch_id_after_journal() {
+        let dir = test_dir("alic-test-journal-batch");
+        let mut journal = Journal::open(&dir);
+        journal.entries.push(JournalEntry {
+            batch_id: u64::MAX / 2,
+            path: String::new(),
+            ops: vec![],
+            timestamp: now(),
+            undone: false,
+        });
+        assert_eq!(next_batch_id(&journal), u64::MAX / 2 + 1);
+        journal.entries.clear();
+        assert!(next_batch_id(&journal) > now() * 1_000_000 - 1_000_000);
+    }
+}
@@ -1,36 +1,78 @@
-use crate::events::emit_add_file;
+use crate::backup::{JournalAction, Recorder};
+use crate::error::Error;
+use crate::filter::skip_reason;
+use crate::history::{self, hash_file, profile_hash, HistoryEntry, Tracker};
+use crate::jpeg::{compress_jpeg_lossless, compress_jpeg_lossless_in_memory};
+use crate::limits::{check_dimensions, check_file_size, image_limits};
 use crate::macos;
+use crate::metrics::{measure_decoded, QualityMetrics};
+use crate::png::{compress_png, compress_png_in_memory};
+use crate::reduce::{reduce_for_output, PixelReduction};
+use crate::tiff::{
+    compress_tiff, count_pages, encode_tiff, encode_tiff_in_memory, for_each_page, probe_tiff,
+    read_first_page,
+};
+use crate::verify::verify_output;
+use crate::webp::{
+    compress_animated_webp, compress_animated_webp_in_memory, compress_webp,
+    compress_webp_in_memory, is_animated_webp,
+};
 
 use super::settings;
 use caesium;
-use caesium::parameters::CSParameters;
+use caesium::parameters::{CSParameters, ChromaSubsampling, TiffCompression, TiffDeflateLevel};
 use image;
-use image::DynamicImage;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder};
 use serde;
 use specta::Type;
 use std::fs;
+use std::io::Cursor;
 use std::os::unix::fs::MetadataExt;
 
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize, Type)]
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct FileEntry {
     pub path: String,
     pub file: Option<String>,
     pub status: FileEntryStatus,
-    pub size: Option<u32>,
-    pub original_size: Option<u32>,
+    pub size: Option<u64>,
+    pub original_size: Option<u64>,
     pub ext: Option<String>,
-    pub savings: Option<u32>,
+    // Percent of the original size saved, fractional and negative when it grew
+    pub savings: Option<f64>,
     pub error: Option<String>,
+    #[serde(default)]
+    pub out_path: Option<String>,
+    #[serde(default)]
+    pub error_type: Option<CompressErrorType>,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    // From the file's content, once it has been read
+    #[serde(default)]
+    pub image_type: Option<ImageType>,
+    #[serde(default)]
+    pub out_type: Option<ImageType>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize, Type)]
 pub struct FileInfoResult {
-    pub size: u32,
+    pub size: u64,
     pub extension: String,
     pub filename: String,
+    // Estimated peak memory while compressing, in MB
+    pub peak_mb: u32,
+    // 0 if the header can't be read
+    pub width: u32,
+    pub height: u32,
+    pub image_type: Option<ImageType>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize, Type)]
@@ -39,6 +81,7 @@ pub enum FileEntryStatus {
     Compressing,
     Complete,
     AlreadySmaller,
+    Skipped,
     Error,
 }
 
@@ -55,9 +98,28 @@ pub enum ImageType {
 #[serde(rename_all = "camelCase")]
 pub struct CompressResult {
     pub path: String,
-    pub out_size: u32,
+    pub out_size: u64,
     pub out_path: String,
     pub result: String,
+    pub reductions: Vec<PixelReduction>,
+    // Negative when converting made the file bigger
+    pub bytes_saved: i64,
+    pub in_type: ImageType,
+    pub out_type: ImageType,
+    pub in_width: u32,
+    pub in_height: u32,
+    pub out_width: u32,
+    pub out_height: u32,
+    // None when encoded losslessly
+    pub quality: Option<u32>,
+    // EXIF found in the input and not in the output
+    pub metadata_stripped: bool,
+    pub resized: bool,
+    // Pixels we decoded ourselves, libcaesium's own decode counts as encoding
+    pub decode_ms: u64,
+    pub encode_ms: u64,
+    // Only measured when enabled in settings
+    pub metrics: Option<QualityMetrics>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Type)]
@@ -65,9 +127,58 @@ pub struct CompressResult {
 pub struct CompressError {
     pub error: String,
     pub error_type: CompressErrorType,
+    pub path: Option<String>,
+    // Underlying errors, outermost first
+    pub causes: Vec<String>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Type)]
+impl CompressError {
+    pub fn new(error_type: CompressErrorType, error: impl Into<String>) -> Self {
+        CompressError {
+            error: error.into(),
+            error_type,
+            path: None,
+            causes: vec![],
+        }
+    }
+}
+
+impl From<Error> for CompressError {
+    fn from(err: Error) -> Self {
+        let error_type = match &err {
+            Error::Io { source, .. } => match source.kind() {
+                std::io::ErrorKind::NotFound => CompressErrorType::FileNotFound,
+                std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::ReadOnlyFilesystem => {
+                    CompressErrorType::Permission
+                }
+                std::io::ErrorKind::StorageFull | std::io::ErrorKind::QuotaExceeded => {
+                    CompressErrorType::DiskFull
+                }
+                _ => CompressErrorType::Io,
+            },
+            Error::Decode { .. } => CompressErrorType::Decode,
+            Error::Encode { .. } => CompressErrorType::Encode,
+            Error::Unsupported { .. } => CompressErrorType::UnsupportedFileType,
+            Error::LimitsExceeded { .. } => CompressErrorType::LimitsExceeded,
+            Error::Verification { .. } => CompressErrorType::VerificationFailed,
+            Error::Cancelled { .. } => CompressErrorType::Timeout,
+        };
+        let causes = err.causes();
+        // The frontend only shows `error`, so it carries the whole chain
+        let error = std::iter::once(err.to_string())
+            .chain(causes.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(": ");
+        CompressError {
+            error,
+            error_type,
+            path: Some(err.path().to_string()),
+            causes,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize, Type)]
 pub enum CompressErrorType {
     Unknown,
     FileTooLarge,
@@ -75,13 +186,167 @@ pub enum CompressErrorType {
     UnsupportedFileType,
     WontOverwrite,
     NotSmaller,
+    LimitsExceeded,
+    Timeout,
+    VerificationFailed,
+    Io,
+    Permission,
+    DiskFull,
+    Decode,
+    Encode,
+    Skipped,
+    AlreadyProcessed,
+    // Asked for something that isn't there, like a profile or something to undo
+    Invalid,
 }
 
 #[tauri::command]
 #[specta::specta]
 pub async fn process_img(
+    app: tauri::AppHandle,
     parameters: settings::ProfileData,
     file: FileEntry,
+) -> Result<CompressResult, CompressError> {
+    let settings = settings::get_settings_data(&app);
+    let temp_path = get_temp_path(&get_out_path(&parameters, &file.path));
+    let history = Tracker::new(&app, !settings.recompress_processed);
+    let mut journal = Recorder::new(&app, settings.backup_originals);
+    // A stuck encode can't be killed, so it's abandoned and told to clean up after itself
+    let state = Arc::new(JobState::new());
+    let path = file.path.clone();
+    let result = run_with_time_limit(&path, settings.time_limit, state.clone(), move || {
+        compress_file(
+            parameters,
+            file,
+            &settings.limits,
+            &history,
+            &mut journal,
+            settings.measure_quality,
+            &state,
+        )
+    })
+    .await;
+    if let Err(CompressError {
+        error_type: CompressErrorType::Timeout,
+        ..
+    }) = result
+    {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// Jobs that timed out but are still running, by path
+static ABANDONED: Mutex<Vec<(String, tauri::async_runtime::JoinHandle<()>)>> =
+    Mutex::new(Vec::new());
+
+const RUNNING: u8 = 0;
+const CANCELLED: u8 = 1;
+const COMMITTING: u8 = 2;
+
+// Whether a job may still write its output, so a timeout can't land halfway through
+struct JobState(AtomicU8);
+
+impl JobState {
+    fn new() -> Self {
+        JobState(AtomicU8::new(RUNNING))
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst) == CANCELLED
+    }
+
+    // False while the output is being moved into place, that has to finish first
+    fn cancel(&self) -> bool {
+        match self
+            .0
+            .compare_exchange(RUNNING, CANCELLED, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => true,
+            Err(state) => state == CANCELLED,
+        }
+    }
+
+    // Call right before trashing or renaming anything, false once cancelled
+    fn begin_commit(&self) -> bool {
+        self.0
+            .compare_exchange(RUNNING, COMMITTING, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    fn end_commit(&self) {
+        self.0.store(RUNNING, Ordering::SeqCst);
+    }
+}
+
+// Runs the job on a blocking thread, 0 seconds waits forever
+async fn run_with_time_limit<F, T>(
+    path: &str,
+    time_limit: u32,
+    state: Arc<JobState>,
+    job: F,
+) -> Result<T, CompressError>
+where
+    F: FnOnce() -> Result<T, CompressError> + Send + 'static,
+    T: Send + 'static,
+{
+    let mut handle = tauri::async_runtime::spawn_blocking(job);
+    let result = if time_limit == 0 {
+        handle.await
+    } else {
+        match tokio::time::timeout(Duration::from_secs(time_limit as u64), &mut handle).await {
+            Ok(result) => result,
+            Err(_) if state.cancel() => {
+                // Kept so the caller can wait for the thread before starting another
+                let waiter = tauri::async_runtime::spawn(async move {
+                    let _ = handle.await;
+                });
+                ABANDONED
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push((path.to_string(), waiter));
+                return Err(CompressError::new(
+                    CompressErrorType::Timeout,
+                    format!("Image took longer than {} seconds.", time_limit),
+                ));
+            }
+            // Already moving its output into place, which is quick
+            Err(_) => handle.await,
+        }
+    };
+    result.unwrap_or_else(|e| {
+        Err(CompressError::new(
+            CompressErrorType::Unknown,
+            e.to_string(),
+        ))
+    })
+}
+
+// Returns once the threads of timed out jobs for `path` have stopped
+#[tauri::command]
+#[specta::specta]
+pub async fn wait_for_job(path: String) {
+    let waiters: Vec<_> = {
+        let mut abandoned = ABANDONED.lock().unwrap_or_else(|e| e.into_inner());
+        let (waiters, rest) = std::mem::take(&mut *abandoned)
+            .into_iter()
+            .partition(|(p, _)| *p == path);
+        *abandoned = rest;
+        waiters
+    };
+    for (_, waiter) in waiters {
+        let _ = waiter.await;
+    }
+}
+
+fn compress_file(
+    parameters: settings::ProfileData,
+    file: FileEntry,
+    limits: &settings::DecodeLimits,
+    history: &Tracker,
+    journal: &mut Recorder,
+    measure_quality: bool,
+    state: &JobState,
 ) -> Result<CompressResult, CompressError> {
     // check file exists,
     // get type,
@@ -96,169 +361,441 @@ pub async fn process_img(
     let out_path = get_out_path(&parameters, &file.path);
 
     if file.path == out_path && !parameters.should_overwrite {
-        return Err(CompressError {
-            error: "Image would be overwritten. Enable Overwrite in settings to allow this."
-                .to_string(),
-            error_type: CompressErrorType::WontOverwrite,
-        });
+        return Err(CompressError::new(
+            CompressErrorType::WontOverwrite,
+            "Image would be overwritten. Enable Overwrite in settings to allow this.",
+        ));
     }
 
-    let original_img = match read_image(&file.path) {
-        Ok(img) => img,
-        Err(err) => {
-            return Err(CompressError {
-                error: err,
-                error_type: CompressErrorType::UnsupportedFileType,
-            })
+    let metadata = fs::metadata(&file.path).map_err(|e| Error::io(&file.path, e))?;
+    let file_size = metadata.len();
+    check_file_size(&file.path, limits, file_size)?;
+
+    // Only the header is read here, the pixels are decoded further down if needed
+    let header = probe_image(&file.path)?;
+    let modified = metadata.modified().map_err(|e| Error::io(&file.path, e))?;
+    if let Some(reason) = skip_reason(
+        &parameters.filters,
+        file_size,
+        header.width,
+        header.height,
+        modified,
+    ) {
+        return Err(CompressError::new(CompressErrorType::Skipped, reason));
+    }
+    // libcaesium has no limits of its own, so this also guards its decode
+    check_dimensions(
+        &file.path,
+        limits,
+        header.width,
+        header.height,
+        header.bytes_per_pixel,
+    )?;
+    let input_hash = hash_file(&file.path).map_err(|e| Error::io(&file.path, e))?;
+    let profile_hash = profile_hash(&parameters);
+    if let Some(reason) = history.skip_reason(&input_hash, &profile_hash) {
+        return Err(CompressError::new(
+            CompressErrorType::AlreadyProcessed,
+            reason,
+        ));
+    }
+    let original_image_type = header.image_type;
+    let (out_type, should_convert) = output_type(&parameters, original_image_type);
+
+    if should_convert
+        && original_image_type == ImageType::TIFF
+        && count_pages(&file.path, limits).unwrap_or(1) > 1
+    {
+        let started = Instant::now();
+        let converted = convert_pages(&parameters, &file.path, limits, out_type, journal, state);
+        // Pages written before a failure can still be undone
+        journal.finish(&file.path);
+        let (page_paths, out_size, reductions) = converted?;
+        let encode_ms = started.elapsed().as_millis() as u64;
+        for page_path in &page_paths {
+            record_history(
+                history,
+                &input_hash,
+                &profile_hash,
+                &file.path,
+                page_path,
+                file_size,
+            );
         }
-    };
+        let csparams = create_csparameters(&parameters, header.width, header.height, false);
+        let out_header = probe_image(&page_paths[0])?;
+        return Ok(CompressResult {
+            bytes_saved: file_size as i64 - out_size as i64,
+            in_type: original_image_type,
+            out_type,
+            in_width: header.width,
+            in_height: header.height,
+            out_width: out_header.width,
+            out_height: out_header.height,
+            quality: effective_quality(&parameters, &csparams, out_type, false),
+            metadata_stripped: has_exif(&file.path) && !has_exif(&page_paths[0]),
+            resized: csparams.width > 0 || csparams.height > 0,
+            decode_ms: 0,
+            encode_ms,
+            metrics: None,
+            path: file.path,
+            out_size,
+            out_path: page_paths[0].clone(),
+            result: format!("Split into {} pages", page_paths.len()),
+            reductions,
+        });
+    }
 
-    let csparams = create_csparameters(&parameters, original_img.width(), original_img.height());
-    drop(original_img);
+    let lossless_jpeg = out_type == ImageType::JPEG && !should_convert && parameters.jpeg.lossless;
+    // The original is decoded once and shared by the text check, our encoders, the
+    // lossless check and the metrics. libcaesium still decodes the file again for a JPEG.
+    let check_text =
+        out_type == ImageType::JPEG && !lossless_jpeg && parameters.jpeg.text_quality_floor > 0;
+    let started = Instant::now();
+    let mut original = match check_text {
+        true => read_image(&file.path, limits).ok(),
+        false => None,
+    };
+    let mut decode_ms = started.elapsed().as_millis() as u64;
+    let has_text = original.as_ref().is_some_and(contains_text);
+    let csparams = create_csparameters(&parameters, header.width, header.height, has_text);
 
-    let original_image_type = match guess_image_type(&file.path) {
-        Ok(img) => img,
-        Err(err) => {
-            return Err(CompressError {
-                error: err,
-                error_type: CompressErrorType::UnsupportedFileType,
-            })
+    // Our own encoders work on pixels, everything else is left to libcaesium
+    let needs_pixels = match out_type {
+        ImageType::PNG => true,
+        ImageType::WEBP => !is_animated_webp(&file.path),
+        ImageType::TIFF => should_convert,
+        ImageType::JPEG | ImageType::GIF => false,
+    };
+    let resized = csparams.width > 0 || csparams.height > 0;
+    let lossless = match out_type {
+        _ if lossless_jpeg => parameters.jpeg.rotation == settings::JpegRotation::None,
+        ImageType::PNG => parameters.png.lossless && !resized,
+        ImageType::WEBP => {
+            parameters.webp.mode == settings::WebpMode::Lossless
+                && !resized
+                && !is_animated_webp(&file.path)
+        }
+        ImageType::TIFF => !resized,
+        ImageType::JPEG | ImageType::GIF => false,
+    };
+    let keep_original = lossless || measure_quality;
+    let started = Instant::now();
+    if original.is_none() && (needs_pixels || keep_original) {
+        match read_image(&file.path, limits) {
+            Ok(img) => original = Some(img),
+            Err(err) if needs_pixels => return Err(err.into()),
+            // A lossless mode is only trusted once its output has been compared
+            Err(err) if lossless => {
+                return Err(Error::verification(
+                    &file.path,
+                    format!("Cannot check the output is lossless: {}", err),
+                )
+                .into())
+            }
+            // The metrics are left out instead
+            Err(_) => {}
         }
+    }
+    decode_ms += started.elapsed().as_millis() as u64;
+    // Only copied when it's still needed after the encode
+    let pixels = match needs_pixels {
+        true if keep_original => original.clone(),
+        true => original.take(),
+        false => None,
     };
-    let should_convert =
-        parameters.should_convert && parameters.convert_extension != original_image_type;
+    if !keep_original {
+        original = None;
+    }
 
+    let mut reductions = vec![];
     let temp_path = get_temp_path(&out_path);
-    let result = if should_convert {
-        convert_image(
+    let started = Instant::now();
+    match pixels {
+        Some(img) => {
+            let img = reduce_for_output(img, out_type, &parameters, &mut reductions);
+            match out_type {
+                ImageType::PNG => {
+                    // Reduced pixels have to be encoded again to be kept
+                    let original_png = (original_image_type == ImageType::PNG
+                        && reductions.is_empty())
+                    .then_some(file.path.as_str());
+                    compress_png(
+                        &temp_path,
+                        img,
+                        original_png,
+                        &csparams,
+                        &parameters.png,
+                        &mut reductions,
+                    )
+                }
+                ImageType::WEBP => compress_webp(&temp_path, img, &csparams, &parameters.webp),
+                _ => encode_tiff(&temp_path, img, &csparams),
+            }
+        }
+        None if lossless_jpeg => compress_jpeg_lossless(&file.path, &temp_path, &parameters.jpeg),
+        None if out_type == ImageType::TIFF => {
+            match compress_tiff(&file.path, &temp_path, &parameters, limits, &mut reductions) {
+                // A layout our reader doesn't know, libcaesium decodes single pages itself
+                Err(Error::Unsupported { .. })
+                    if count_pages(&file.path, limits).is_ok_and(|pages| pages == 1) =>
+                {
+                    compress_image(&file.path, &temp_path, csparams)
+                }
+                result => result,
+            }
+        }
+        // Only an animation gets here, a still WebP is decoded above
+        None if out_type == ImageType::WEBP => {
+            compress_animated_webp(&file.path, &temp_path, &csparams, &parameters.webp, limits)
+        }
+        None if should_convert => convert_image(
             &file.path,
             &temp_path,
             csparams,
             parameters.convert_extension,
-        )
-    } else {
-        compress_image(&file.path, &temp_path, csparams)
-    };
+        ),
+        None => compress_image(&file.path, &temp_path, csparams),
+    }?;
+    let encode_ms = started.elapsed().as_millis() as u64;
 
-    if result.is_err() {
-        return Err(CompressError {
-            error: result.err().unwrap().to_string(),
-            error_type: CompressErrorType::Unknown,
-        });
+    let out_size = fs::metadata(&temp_path)
+        .map_err(|e| Error::io(&temp_path, e))?
+        .size();
+    let original_size = file.original_size.expect("Image size needs to be set");
+
+    if !parameters.should_convert && !saves_enough(original_size, out_size) {
+        let _ = fs::remove_file(temp_path);
+        return Err(CompressError::new(
+            CompressErrorType::NotSmaller,
+            "Image cannot be compressed further.",
+        ));
     }
 
-    let temp_metadata_result = std::fs::metadata(&temp_path);
-    let temp_size: f64 = match temp_metadata_result {
-        Ok(result) => result.size() as f64,
-        Err(e) => {
-            return Err(CompressError {
-                error: e.to_string(),
-                error_type: CompressErrorType::FileNotFound,
-            })
-        }
+    let (width, height) = (header.width, header.height);
+    let mut dimensions = match lossless_jpeg {
+        true => vec![(width, height)],
+        false => vec![resized_dimensions(
+            width,
+            height,
+            csparams.width,
+            csparams.height,
+        )],
     };
-    let original_size = file.original_size.expect("Image size needs to be set") as f64;
-
-    if !parameters.should_convert && temp_size > original_size * 0.95 {
+    if original_image_type == ImageType::JPEG {
+        // Rotated by the lossless mode, or by libcaesium following the EXIF orientation
+        if !lossless_jpeg {
+            dimensions.push(resized_dimensions(
+                width,
+                height,
+                csparams.height,
+                csparams.width,
+            ));
+        }
+        let transposed: Vec<_> = dimensions.iter().map(|&(w, h)| (h, w)).collect();
+        dimensions.extend(transposed);
+    }
+    let source = original.as_ref().filter(|_| lossless);
+    if let Err(err) = verify_output(&temp_path, out_type, &dimensions, source, limits) {
         let _ = fs::remove_file(temp_path);
-        return Err(CompressError {
-            error: "Image cannot be compressed further.".to_string(),
-            error_type: CompressErrorType::NotSmaller,
-        });
+        return Err(err.into());
+    }
+    if !measure_quality {
+        original = None;
     }
+    let out_header = probe_image(&temp_path)?;
+    // These have to happen before an overwritten original goes to the trash
+    let metadata_stripped = has_exif(&file.path) && !has_exif(&temp_path);
+    let metrics = original.and_then(|original| {
+        let output = read_image(&temp_path, limits).ok()?;
+        measure_decoded(&file.path, original, &output, limits)
+            .ok()
+            .map(|(metrics, _)| metrics)
+    });
 
-    if out_path == file.path {
-        let res = macos::trash_file(&file.path);
-        if res.is_err() {
-            return Err(CompressError {
-                error: res.err().unwrap().to_string(),
-                error_type: CompressErrorType::Unknown,
-            });
-        }
+    // Timed out, the caller has already given up on this file
+    if state.is_cancelled() {
+        let _ = fs::remove_file(temp_path);
+        return Err(Error::Cancelled { path: file.path }.into());
     }
 
-    let rename_result = fs::rename(temp_path, &out_path);
-    match rename_result {
-        Ok(_) => {}
-        Err(e) => {
-            return Err(CompressError {
-                error: e.to_string(),
-                error_type: CompressErrorType::Unknown,
-            })
-        }
+    // Backups are made first, a file that can't be backed up is left alone
+    let backed_up = match out_path == file.path {
+        true => journal
+            .before(JournalAction::Trash, &file.path)
+            .and_then(|_| journal.before(JournalAction::Create, &out_path)),
+        false if Path::new(&out_path).exists() => journal.before(JournalAction::Replace, &out_path),
+        false => journal.before(JournalAction::Create, &out_path),
     };
-    let out_size = temp_size as u32;
+    if let Err(e) = backed_up {
+        let _ = fs::remove_file(&temp_path);
+        return Err(Error::io(&out_path, e).into());
+    }
+
+    // Checked again after the backups, nothing may be moved once the caller gave up
+    if !state.begin_commit() {
+        let _ = fs::remove_file(temp_path);
+        return Err(Error::Cancelled { path: file.path }.into());
+    }
+    commit_output(&file.path, &temp_path, &out_path, journal)?;
+    record_history(
+        history,
+        &input_hash,
+        &profile_hash,
+        &file.path,
+        &out_path,
+        file_size,
+    );
     Ok(CompressResult {
+        bytes_saved: file_size as i64 - out_size as i64,
+        in_type: original_image_type,
+        out_type,
+        in_width: header.width,
+        in_height: header.height,
+        out_width: out_header.width,
+        out_height: out_header.height,
+        quality: effective_quality(&parameters, &csparams, out_type, lossless_jpeg),
+        metadata_stripped,
+        resized,
+        decode_ms,
+        encode_ms,
+        metrics,
         path: file.path,
         out_size,
         out_path,
         result: "Success".to_string(),
+        reductions,
     })
 }
 
-// #[derive(Debug)]
-// struct ScanError {
-//     error: String,
-//     path: String,
-// }
-
-// pub fn scan_directory_for_images(
-//     path: impl Into<PathBuf>,
-// ) -> impl Stream<Item = Result<PathBuf, ScanError>> {
-//     let (tx, rx) = mpsc::channel(100);
-//     let path = path.into();
-//     if !path.is_dir() {
-//         if is_image(&path) {
-//             tx.send(Ok(path));
-//         } else {
-//             tx.send(Err(ScanError {
-//                 path: path.to_string_lossy().to_string(),
-//                 error: "Not a directory or image".to_string(),
-//             }));
-//         }
-//         return ReceiverStream::new(rx);
-//     }
-//     tokio::spawn(async move {
-//         async fn visit_dirs(
-//             dir: PathBuf,
-//             tx: mpsc::Sender<Result<PathBuf, ScanError>>,
-//         ) -> Result<(), String> {
-//             let read_dir = tokio::fs::read_dir(&dir).await.map_err(|e| e.to_string())?;
-
-//             let mut read_dir = read_dir;
-//             while let Some(entry) = read_dir.next_entry().await.map_err(|e| e.to_string())? {
-//                 let path = entry.path();
-//                 if is_image(&path) {
-//                     if tx.send(Ok(path)).await.is_err() {
-//                         break;
-//                     }
-//                 } else if path.is_dir() {
-//                     let future = visit_dirs(path.clone(), tx.clone());
-//                     if let Err(e) = Box::pin(future).await {
-//                         let _ = tx
-//                             .send(Err(ScanError {
-//                                 path: path.to_string_lossy().to_string(),
-//                                 error: e,
-//                             }))
-//                             .await;
-//                     }
-//                 }
-//             }
-//             Ok(())
-//         }
-
-//         if let Err(e) = Box::pin(visit_dirs(path.clone(), tx.clone())).await {
-//             let _ = tx
-//                 .send(Err(ScanError {
-//                     path: path.to_string_lossy().to_string(),
-//                     error: e,
-//                 }))
-//                 .await;
-//         }
-//     });
-
-//     ReceiverStream::new(rx)
-// }
+// The type to encode to, and whether that is a conversion
+fn output_type(parameters: &settings::ProfileData, image_type: ImageType) -> (ImageType, bool) {
+    match parameters.should_convert && parameters.convert_extension != image_type {
+        true => (parameters.convert_extension, true),
+        false => (image_type, false),
+    }
+}
+
+// Encodes like compress_file without touching the disk, multi-page TIFFs only get
+// their first page. `original` is the decoded input, so it can be reused between calls.
+pub fn encode_in_memory(
+    parameters: &settings::ProfileData,
+    path: &str,
+    original: &DynamicImage,
+    limits: &settings::DecodeLimits,
+) -> Result<(Vec<u8>, ImageType), Error> {
+    let image_type = guess_image_type(path)?;
+    let (out_type, should_convert) = output_type(parameters, image_type);
+    let lossless_jpeg = out_type == ImageType::JPEG && !should_convert && parameters.jpeg.lossless;
+    let has_text = out_type == ImageType::JPEG
+        && !lossless_jpeg
+        && parameters.jpeg.text_quality_floor > 0
+        && contains_text(original);
+    let csparams = create_csparameters(parameters, original.width(), original.height(), has_text);
+    let read = || fs::read(path).map_err(|e| Error::io(path, e));
+    let reduced = || reduce_for_output(original.clone(), out_type, parameters, &mut vec![]);
+    let bytes = match out_type {
+        ImageType::PNG => {
+            let mut reductions = vec![];
+            let image = reduce_for_output(original.clone(), out_type, parameters, &mut reductions);
+            let original_png =
+                (image_type == ImageType::PNG && reductions.is_empty()).then_some(path);
+            compress_png_in_memory(
+                path,
+                image,
+                original_png,
+                &csparams,
+                &parameters.png,
+                &mut reductions,
+            )?
+        }
+        ImageType::WEBP if !is_animated_webp(path) => {
+            compress_webp_in_memory(path, reduced(), &csparams, &parameters.webp)?
+        }
+        ImageType::WEBP if !should_convert => {
+            compress_animated_webp_in_memory(path, &csparams, &parameters.webp, limits)?
+        }
+        ImageType::TIFF => encode_tiff_in_memory(path, reduced(), &csparams)?,
+        ImageType::JPEG if lossless_jpeg => {
+            compress_jpeg_lossless_in_memory(path, &parameters.jpeg)?
+        }
+        // libcaesium can only compress GIFs on disk
+        ImageType::GIF => {
+            return Err(Error::unsupported(
+                path,
+                "GIF output can't be encoded in memory",
+            ))
+        }
+        _ if should_convert => {
+            caesium::convert_in_memory(read()?, &csparams, to_supported_type(out_type))
+                .map_err(|e| Error::encode(path, e.to_string()))?
+        }
+        _ => caesium::compress_in_memory(read()?, &csparams)
+            .map_err(|e| Error::encode(path, e.to_string()))?,
+    };
+    Ok((bytes, out_type))
+}
+
+// The quality the encoder was given, text floor included
+fn effective_quality(
+    parameters: &settings::ProfileData,
+    csparams: &CSParameters,
+    out_type: ImageType,
+    lossless_jpeg: bool,
+) -> Option<u32> {
+    match out_type {
+        ImageType::JPEG if lossless_jpeg => None,
+        ImageType::JPEG => Some(csparams.jpeg.quality),
+        ImageType::PNG if parameters.png.lossless => None,
+        ImageType::PNG => Some(csparams.png.quality),
+        ImageType::WEBP if parameters.webp.mode == settings::WebpMode::Lossless => None,
+        ImageType::WEBP => Some(csparams.webp.quality),
+        ImageType::GIF => Some(csparams.gif.quality),
+        ImageType::TIFF => None,
+    }
+}
+
+// A lone orientation doesn't count, lossless JPEGs keep it to show the right way up
+fn has_exif(path: &str) -> bool {
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .is_ok_and(|exif| {
+            exif.fields()
+                .any(|field| field.tag != exif::Tag::Orientation)
+        })
+}
+
+// Outputs are recorded too, so dropping them back in doesn't compress them again
+fn record_history(
+    history: &Tracker,
+    input_hash: &str,
+    profile_hash: &str,
+    path: &str,
+    out_path: &str,
+    input_size: u64,
+) {
+    let (Ok(output_hash), Ok(metadata)) = (hash_file(out_path), fs::metadata(out_path)) else {
+        return;
+    };
+    history.record(HistoryEntry {
+        input_hash: input_hash.to_string(),
+        output_hash,
+        profile_hash: profile_hash.to_string(),
+        path: path.to_string(),
+        out_path: out_path.to_string(),
+        input_size,
+        output_size: metadata.len(),
+        timestamp: history::now(),
+    });
+}
+
+// Has to save at least 5%, in integers so sizes over 4 GB stay exact
+fn saves_enough(original_size: u64, out_size: u64) -> bool {
+    out_size <= original_size - original_size / 20
+}
 
 fn get_temp_path(path: &str) -> String {
     // /original/path/test.png -> /original/path/.test.png
@@ -271,17 +808,59 @@ fn get_temp_path(path: &str) -> String {
         .to_string()
 }
 
-fn read_image(path: &str) -> Result<DynamicImage, String> {
-    let image = image::open(&path);
-    match image {
+pub fn read_image(path: &str, limits: &settings::DecodeLimits) -> Result<DynamicImage, Error> {
+    let mut reader = image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| Error::io(path, e))?;
+    reader.limits(image_limits(limits));
+    match reader.decode() {
         Ok(image) => Ok(image),
-        Err(err) => Err(format!("Error: {}", err)),
+        // Palette and bilevel pages are only read by our own TIFF reader
+        Err(err) => match guess_image_type(path) {
+            Ok(ImageType::TIFF) => read_first_page(path, limits),
+            _ => Err(Error::image(path, err)),
+        },
     }
 }
 
-fn guess_image_type(path: &str) -> Result<ImageType, String> {
-    let kind =
-        infer::get_from_path(path).map_err(|e| format!("Error determining file type: {}", e))?;
+struct ImageHeader {
+    image_type: ImageType,
+    width: u32,
+    height: u32,
+    bytes_per_pixel: u64,
+}
+
+// Reads the format and dimensions without decoding the pixels
+fn probe_image(path: &str) -> Result<ImageHeader, Error> {
+    let image_type = guess_image_type(path)?;
+    let decoder = image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| Error::io(path, e))?
+        .into_decoder();
+    let decoder = match decoder {
+        Ok(decoder) => decoder,
+        Err(_) if image_type == ImageType::TIFF => {
+            let header = probe_tiff(path)?;
+            return Ok(ImageHeader {
+                image_type,
+                width: header.width,
+                height: header.height,
+                bytes_per_pixel: header.bytes_per_pixel(),
+            });
+        }
+        Err(err) => return Err(Error::image(path, err)),
+    };
+    let (width, height) = decoder.dimensions();
+    Ok(ImageHeader {
+        image_type,
+        width,
+        height,
+        bytes_per_pixel: decoder.color_type().bytes_per_pixel() as u64,
+    })
+}
+
+pub fn guess_image_type(path: &str) -> Result<ImageType, Error> {
+    let kind = infer::get_from_path(path).map_err(|e| Error::io(path, e))?;
     match kind {
         Some(kind) => match kind.mime_type() {
             "image/jpeg" => Ok(ImageType::JPEG),
@@ -289,30 +868,49 @@ fn guess_image_type(path: &str) -> Result<ImageType, String> {
             "image/webp" => Ok(ImageType::WEBP),
             "image/gif" => Ok(ImageType::GIF),
             "image/tiff" => Ok(ImageType::TIFF),
-            _ => Err(format!(
-                "Error: Unsupported image type: {}",
-                kind.mime_type()
+            _ => Err(Error::unsupported(
+                path,
+                format!("Unsupported image type: {}", kind.mime_type()),
             )),
         },
-        None => Err("Error: Could not determine image type.".to_string()),
+        None => Err(Error::unsupported(path, "Could not determine image type.")),
     }
 }
 
 fn get_out_path(parameters: &settings::ProfileData, path: &str) -> String {
-    let path = Path::new(&path);
-    let extension = match parameters.should_convert {
-        true => image_type_to_extension(parameters.convert_extension),
-        false => path
-            .extension()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_string(),
+    get_out_path_with_suffix(parameters, path, "")
+}
+
+// /scans/doc.tiff -> /scans/doc-2.min.png
+fn get_page_out_path(parameters: &settings::ProfileData, path: &str, page: usize) -> String {
+    get_out_path_with_suffix(parameters, path, &format!("-{}", page))
+}
+
+fn get_out_path_with_suffix(
+    parameters: &settings::ProfileData,
+    path: &str,
+    suffix: &str,
+) -> String {
+    let extension = match (parameters.should_convert, Path::new(path).extension()) {
+        (true, _) => image_type_to_extension(parameters.convert_extension),
+        (false, Some(extension)) => extension.to_string_lossy().to_string(),
+        // Extensionless files get the extension of what they contain
+        (false, None) => guess_image_type(path)
+            .map(image_type_to_extension)
+            .unwrap_or_default(),
     };
+    let path = Path::new(&path);
     let posfix = match parameters.add_posfix {
         true => parameters.postfix.clone(),
         false => "".to_string(),
     };
-    format!("{}{}.{}", remove_extension(&path), posfix, extension)
+    format!(
+        "{}{}{}.{}",
+        remove_extension(&path),
+        suffix,
+        posfix,
+        extension
+    )
 }
 
 fn image_type_to_extension(image_type: ImageType) -> String {
@@ -325,10 +923,11 @@ fn image_type_to_extension(image_type: ImageType) -> String {
     }
 }
 
-fn create_csparameters(
+pub fn create_csparameters(
     parameters: &settings::ProfileData,
     width: u32,
     height: u32,
+    has_text: bool,
 ) -> CSParameters {
     let mut new_height = 0;
     let mut new_width = 0;
@@ -347,20 +946,114 @@ fn create_csparameters(
 
     let mut cspars = CSParameters::new();
     cspars.jpeg.quality = parameters.jpeg_quality;
+    if has_text {
+        cspars.jpeg.quality = cspars
+            .jpeg
+            .quality
+            .max(parameters.jpeg.text_quality_floor.min(100));
+    }
+    cspars.jpeg.progressive = parameters.jpeg.progressive;
+    cspars.jpeg.chroma_subsampling = match parameters.jpeg.chroma_subsampling {
+        settings::ChromaSubsampling::CS444 => ChromaSubsampling::CS444,
+        settings::ChromaSubsampling::CS422 => ChromaSubsampling::CS422,
+        settings::ChromaSubsampling::CS420 => ChromaSubsampling::CS420,
+        settings::ChromaSubsampling::Auto => ChromaSubsampling::Auto,
+    };
     cspars.png.quality = parameters.png_quality;
+    cspars.png.optimization_level = parameters.png.optimization_level.min(6) as u8;
     cspars.webp.quality = parameters.webp_quality;
+    cspars.tiff.algorithm = match parameters.tiff.compression {
+        settings::TiffCompression::None => TiffCompression::Uncompressed,
+        settings::TiffCompression::Lzw => TiffCompression::Lzw,
+        settings::TiffCompression::Deflate => TiffCompression::Deflate,
+        settings::TiffCompression::PackBits => TiffCompression::Packbits,
+    };
+    cspars.tiff.deflate_level = match parameters.tiff.deflate_level {
+        settings::TiffDeflateLevel::Fast => TiffDeflateLevel::Fast,
+        settings::TiffDeflateLevel::Balanced => TiffDeflateLevel::Balanced,
+        settings::TiffDeflateLevel::Best => TiffDeflateLevel::Best,
+    };
     cspars.gif.quality = parameters.gif_quality;
     cspars.width = new_width;
     cspars.height = new_height;
     cspars
 }
 
-fn compress_image(path: &str, out_path: &str, mut params: CSParameters) -> Result<String, String> {
-    let result = caesium::compress(path.to_string(), out_path.to_string(), &mut params);
-    match result {
-        Ok(_) => Ok("Success".to_string()),
-        Err(err) => Err(format!("Error: {}", err)),
+// Text and screenshots have large flat areas broken by hard edges,
+// photos have neither.
+fn contains_text(img: &DynamicImage) -> bool {
+    let gray = if img.width() > 1024 || img.height() > 1024 {
+        img.resize(1024, 1024, FilterType::Triangle).to_luma8()
+    } else {
+        img.to_luma8()
+    };
+    let total = (gray.width() * gray.height()) as f64;
+    if total == 0.0 {
+        return false;
     }
+
+    let mut histogram = [0u32; 32];
+    let mut edges = 0u32;
+    for (x, y, pixel) in gray.enumerate_pixels() {
+        histogram[(pixel[0] / 8) as usize] += 1;
+        if x + 1 < gray.width() {
+            let next = gray.get_pixel(x + 1, y)[0];
+            if pixel[0].abs_diff(next) > 96 {
+                edges += 1;
+            }
+        }
+    }
+    histogram.sort_unstable_by(|a, b| b.cmp(a));
+    let dominant = (histogram[0] + histogram[1]) as f64 / total;
+    let edge_ratio = edges as f64 / total;
+    dominant > 0.6 && edge_ratio > 0.02
+}
+
+pub fn resize_image(image: DynamicImage, width: u32, height: u32) -> DynamicImage {
+    if width == 0 && height == 0 {
+        return image;
+    }
+    let (width, height) = resized_dimensions(image.width(), image.height(), width, height);
+    image.resize_exact(width, height, FilterType::Lanczos3)
+}
+
+fn resized_dimensions(width: u32, height: u32, new_width: u32, new_height: u32) -> (u32, u32) {
+    let ratio = width as f64 / height as f64;
+    let (width, height) = match (new_width, new_height) {
+        (0, 0) => (width, height),
+        (0, height) => ((height as f64 * ratio).round() as u32, height),
+        (width, 0) => (width, (width as f64 / ratio).round() as u32),
+        (width, height) => (width, height),
+    };
+    (width.max(1), height.max(1))
+}
+
+// CaesiumError has no std::error::Error impl, only its message is kept
+// Moves the output into place. Once the original is in the trash the journal is
+// written whatever happens next, so undo can still bring it back.
+fn commit_output(
+    path: &str,
+    temp_path: &str,
+    out_path: &str,
+    journal: &mut Recorder,
+) -> Result<(), Error> {
+    if out_path == path {
+        if let Err(e) = macos::trash_file(path) {
+            let _ = fs::remove_file(temp_path);
+            return Err(Error::io(path, std::io::Error::other(e)));
+        }
+    }
+    let renamed = fs::rename(temp_path, out_path);
+    if renamed.is_err() {
+        let _ = fs::remove_file(temp_path);
+    }
+    journal.finish(path);
+    renamed.map_err(|e| Error::io(out_path, e))
+}
+
+fn compress_image(path: &str, out_path: &str, mut params: CSParameters) -> Result<(), Error> {
+    caesium::compress(path.to_string(), out_path.to_string(), &mut params)
+        .map_err(|e| Error::encode(out_path, e.to_string()))
 }
 
 fn convert_image(
@@ -368,24 +1061,114 @@ fn convert_image(
     out_path: &str,
     mut params: CSParameters,
     image_type: ImageType,
-) -> Result<String, String> {
-    let supported_type = match image_type {
+) -> Result<(), Error> {
+    caesium::convert(
+        path.to_string(),
+        out_path.to_string(),
+        &mut params,
+        to_supported_type(image_type),
+    )
+    .map_err(|e| Error::encode(out_path, e.to_string()))
+}
+
+fn to_supported_type(image_type: ImageType) -> caesium::SupportedFileTypes {
+    match image_type {
         ImageType::JPEG => caesium::SupportedFileTypes::Jpeg,
         ImageType::PNG => caesium::SupportedFileTypes::Png,
         ImageType::WEBP => caesium::SupportedFileTypes::WebP,
         ImageType::GIF => caesium::SupportedFileTypes::Gif,
         ImageType::TIFF => caesium::SupportedFileTypes::Tiff,
-    };
-    let result = caesium::convert(
-        path.to_string(),
-        out_path.to_string(),
-        &mut params,
-        supported_type,
-    );
+    }
+}
+
+// Writes each page of a multi-page TIFF to its own file
+fn convert_pages(
+    parameters: &settings::ProfileData,
+    path: &str,
+    limits: &settings::DecodeLimits,
+    out_type: ImageType,
+    journal: &mut Recorder,
+    state: &JobState,
+) -> Result<(Vec<String>, u64, Vec<PixelReduction>), Error> {
+    let mut page_paths = vec![];
+    let mut out_size = 0;
+    let mut reductions = vec![];
+    for_each_page(path, limits, |index, page| {
+        let page_path = get_page_out_path(parameters, path, index + 1);
+        let temp_path = get_temp_path(&page_path);
+        let params = create_csparameters(parameters, page.width(), page.height(), false);
+        let dimensions =
+            resized_dimensions(page.width(), page.height(), params.width, params.height);
+        let page = reduce_for_output(page, out_type, parameters, &mut reductions);
+        convert_page(
+            page,
+            &temp_path,
+            params,
+            parameters,
+            out_type,
+            &mut reductions,
+        )?;
+        if let Err(err) = verify_output(&temp_path, out_type, &[dimensions], None, limits) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err);
+        }
+        if state.is_cancelled() {
+            let _ = fs::remove_file(&temp_path);
+            return Err(Error::Cancelled {
+                path: path.to_string(),
+            });
+        }
+        out_size += fs::metadata(&temp_path)
+            .map_err(|e| Error::io(&temp_path, e))?
+            .size();
+        let action = match Path::new(&page_path).exists() {
+            true => JournalAction::Replace,
+            false => JournalAction::Create,
+        };
+        if let Err(e) = journal.before(action, &page_path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(Error::io(&page_path, e));
+        }
+        if !state.begin_commit() {
+            let _ = fs::remove_file(&temp_path);
+            return Err(Error::Cancelled {
+                path: path.to_string(),
+            });
+        }
+        let renamed = fs::rename(&temp_path, &page_path);
+        state.end_commit();
+        renamed.map_err(|e| Error::io(&page_path, e))?;
+        page_paths.push(page_path);
+        Ok(())
+    })?;
+    Ok((page_paths, out_size, reductions))
+}
 
-    match result {
-        Ok(_) => Ok("Success".to_string()),
-        Err(err) => Err(format!("Error: {}", err)),
+fn convert_page(
+    page: DynamicImage,
+    out_path: &str,
+    params: CSParameters,
+    parameters: &settings::ProfileData,
+    out_type: ImageType,
+    reductions: &mut Vec<PixelReduction>,
+) -> Result<(), Error> {
+    match out_type {
+        ImageType::PNG => compress_png(out_path, page, None, &params, &parameters.png, reductions),
+        ImageType::WEBP => compress_webp(out_path, page, &params, &parameters.webp),
+        ImageType::TIFF => encode_tiff(out_path, page, &params),
+        ImageType::JPEG | ImageType::GIF => {
+            let page = match page.color().has_alpha() {
+                true => DynamicImage::ImageRgba8(page.to_rgba8()),
+                false => DynamicImage::ImageRgb8(page.to_rgb8()),
+            };
+            let mut buffer = Vec::new();
+            page.write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::Png)
+                .map_err(|e| Error::encode(out_path, e))?;
+            let converted =
+                caesium::convert_in_memory(buffer, &params, to_supported_type(out_type))
+                    .map_err(|e| Error::encode(out_path, e.to_string()))?;
+            fs::write(out_path, converted).map_err(|e| Error::io(out_path, e))
+        }
     }
 }
 
@@ -406,65 +1189,10 @@ fn remove_extension(path: &Path) -> String {
 
 #[tauri::command]
 #[specta::specta]
-pub async fn get_all_images(app: tauri::AppHandle, path: String) -> Result<(), String> {
-    let on_event = |path: String| emit_add_file(&app, path);
-
-    let file = Path::new(&path);
-    if !file.exists() {
-        return Ok(());
-    }
-    if file.is_file() {
-        if !is_image(&file) {
-            return Ok(());
-        }
-        on_event(path);
-        return Ok(());
-    }
-    find_images(path, &on_event);
-    Ok(())
-}
-
-fn find_images<P: AsRef<Path>, F>(directory: P, on_event: &F)
-where
-    F: Fn(String),
-{
-    let entries = match fs::read_dir(directory) {
-        Ok(entries) => entries,
-        Err(_) => return,
-    };
-
-    for entry in entries {
-        let path = match entry {
-            Ok(e) => e.path(),
-            Err(_) => continue,
-        };
-
-        if path.is_dir() {
-            // Recursively search subdirectories
-            find_images(&path, on_event);
-        } else if is_image(&path) {
-            on_event(path.to_string_lossy().to_string());
-        }
-    }
-}
-
-#[tauri::command]
-#[specta::specta]
-pub async fn get_file_info(path: &str) -> Result<FileInfoResult, String> {
-    let metadata_result = std::fs::metadata(&path);
-    let size: u32;
-    match metadata_result {
-        Ok(metadata) => {
-            if let Ok(_size) = metadata.len().try_into() {
-                size = _size;
-            } else {
-                return Err("File too large".to_string());
-            }
-        }
-        Err(err) => {
-            return Err(format!("Error getting file size: {}", err));
-        }
-    }
+pub async fn get_file_info(path: &str) -> Result<FileInfoResult, CompressError> {
+    let size = std::fs::metadata(path)
+        .map_err(|e| Error::io(path, e))?
+        .len();
 
     let _path = Path::new(&path);
 
@@ -475,24 +1203,31 @@ pub async fn get_file_info(path: &str) -> Result<FileInfoResult, String> {
         .to_string();
 
     let filename = _path.file_name().unwrap().to_string_lossy().to_string();
+    let peak_mb = estimate_peak_mb(path).unwrap_or(0);
+    let header = probe_image(path).ok();
+    let (width, height) = header.as_ref().map_or((0, 0), |h| (h.width, h.height));
 
     Ok(FileInfoResult {
         size,
         extension,
         filename,
+        peak_mb,
+        width,
+        height,
+        image_type: header.map(|h| h.image_type),
     })
 }
 
-fn is_image(path: &Path) -> bool {
-    if !path.is_file() {
-        return false;
-    }
-    let supported_exts = ["png", "jpeg", "jpg", "gif", "webp", "tiff"];
-    let ext = path.extension().unwrap_or_default();
-    if !supported_exts.contains(&ext.to_str().unwrap()) {
-        return false;
-    }
-    true
+// At most this many images of the original's size are held at once: the original
+// kept for the metrics or the lossless check, the encoder's copy of it, then the
+// decoded output and the copies made comparing the two
+const PEAK_COPIES: u64 = 4;
+
+// width x height x bytes per pixel, from the header only
+fn estimate_peak_mb(path: &str) -> Result<u32, Error> {
+    let header = probe_image(path)?;
+    let bytes = header.width as u64 * header.height as u64 * header.bytes_per_pixel * PEAK_COPIES;
+    Ok(bytes.div_ceil(1024 * 1024).min(u32::MAX as u64) as u32)
 }
 
 #[cfg(test)]
@@ -511,6 +1246,122 @@ mod tests {
         assert_eq!(result.unwrap(), ImageType::JPEG);
     }
 
+    #[test]
+    fn test_probe_image() {
+        let header = probe_image("test/test.jpg").unwrap();
+        let image = read_image("test/test.jpg", &settings::DecodeLimits::default()).unwrap();
+        assert_eq!(header.image_type, ImageType::JPEG);
+        assert_eq!(
+            (header.width, header.height),
+            (image.width(), image.height())
+        );
+    }
+
+    #[test]
+    fn bench_probe_image_vs_decode() {
+        // Cut right after the scan header, so anything that decodes pixels has to fail
+        let data = fs::read("test/test.jpg").unwrap();
+        let sos = data.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+        let scan = sos + 2 + u16::from_be_bytes([data[sos + 2], data[sos + 3]]) as usize;
+        let path = std::env::temp_dir().join("alic-test-probe-header.jpg");
+        fs::write(&path, &data[..scan]).unwrap();
+        let path = path.to_str().unwrap();
+
+        let header = probe_image(path).unwrap();
+        let full = probe_image("test/test.jpg").unwrap();
+        assert_eq!((header.width, header.height), (full.width, full.height));
+        assert!(scan < data.len() / 10);
+        assert!(read_image(path, &settings::DecodeLimits::default()).is_err());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_estimate_peak_mb() {
+        let image = read_image("test/test.jpg", &settings::DecodeLimits::default()).unwrap();
+        let bytes = image.width() as u64 * image.height() as u64 * 3 * PEAK_COPIES;
+        let expected = bytes.div_ceil(1024 * 1024) as u32;
+        assert_eq!(estimate_peak_mb("test/test.jpg").unwrap(), expected);
+    }
+
+    #[test]
+    fn test_commit_output_failure() {
+        let dir = std::env::temp_dir().join("alic-test-commit");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.png").to_string_lossy().to_string();
+        let temp_path = get_temp_path(&path);
+        fs::write(&path, "original").unwrap();
+        fs::write(&temp_path, "compressed").unwrap();
+        // The folder doesn't exist, so the rename fails
+        let out_path = dir.join("missing/a.png").to_string_lossy().to_string();
+
+        let mut journal = Recorder::in_dir(&dir, false);
+        journal.before(JournalAction::Create, &out_path).unwrap();
+        let err = commit_output(&path, &temp_path, &out_path, &mut journal).unwrap_err();
+        assert_eq!(err.path(), out_path);
+        assert!(!Path::new(&temp_path).exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        let recorded = fs::read_to_string(dir.join("journal.jsonl")).unwrap();
+        assert!(recorded.contains(&out_path));
+    }
+
+    #[test]
+    fn test_run_with_time_limit() {
+        let run = |state: Arc<JobState>, job: fn(&JobState) -> Result<u32, CompressError>| {
+            tauri::async_runtime::block_on(async {
+                let result = {
+                    let state = state.clone();
+                    run_with_time_limit("a.png", 1, state.clone(), move || job(&state)).await
+                };
+                wait_for_job("a.png".to_string()).await;
+                result
+            })
+        };
+        let state = Arc::new(JobState::new());
+        assert_eq!(run(state, |_| Ok(1)).ok(), Some(1));
+
+        // Cancelled, and only returned from waiting once the thread is done
+        let state = Arc::new(JobState::new());
+        let result = run(state.clone(), |state| {
+            std::thread::sleep(Duration::from_secs(2));
+            Ok(match state.begin_commit() {
+                true => 1,
+                false => 2,
+            })
+        });
+        assert!(matches!(
+            result,
+            Err(CompressError {
+                error_type: CompressErrorType::Timeout,
+                ..
+            })
+        ));
+        assert!(state.is_cancelled());
+        assert!(ABANDONED.lock().unwrap().is_empty());
+
+        // Already writing its output when the time ran out
+        let state = Arc::new(JobState::new());
+        let result = run(state, |state| {
+            assert!(state.begin_commit());
+            std::thread::sleep(Duration::from_secs(2));
+            Ok(1)
+        });
+        assert_eq!(result.ok(), Some(1));
+    }
+
+    #[test]
+    fn test_compress_error_from_error() {
+        let err = Error::io(
+            "a.png",
+            std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+        );
+        let result = CompressError::from(err);
+        assert!(matches!(result.error_type, CompressErrorType::Permission));
+        assert_eq!(result.path.as_deref(), Some("a.png"));
+        assert_eq!(result.causes.len(), 1);
+        assert!(result.error.starts_with("Cannot access a.png: "));
+    }
+
     #[test]
     fn test_get_out_path() {
         let mut parameters = settings::ProfileData::new();
@@ -544,11 +1395,167 @@ mod tests {
         assert_eq!(result, "test/test.bong.jpeg".to_string());
     }
 
+    #[test]
+    fn test_create_csparameters_jpeg() {
+        let mut parameters = settings::ProfileData::new();
+        let mut result = create_csparameters(&parameters, 100, 100, false);
+        assert_eq!(result.jpeg.quality, 80);
+        assert!(result.jpeg.progressive);
+        assert!(result.jpeg.chroma_subsampling == ChromaSubsampling::Auto);
+
+        parameters.jpeg.progressive = false;
+        parameters.jpeg.chroma_subsampling = settings::ChromaSubsampling::CS444;
+        result = create_csparameters(&parameters, 100, 100, false);
+        assert!(!result.jpeg.progressive);
+        assert!(result.jpeg.chroma_subsampling == ChromaSubsampling::CS444);
+
+        parameters.jpeg.chroma_subsampling = settings::ChromaSubsampling::CS422;
+        result = create_csparameters(&parameters, 100, 100, false);
+        assert!(result.jpeg.chroma_subsampling == ChromaSubsampling::CS422);
+
+        parameters.jpeg.chroma_subsampling = settings::ChromaSubsampling::CS420;
+        result = create_csparameters(&parameters, 100, 100, false);
+        assert!(result.jpeg.chroma_subsampling == ChromaSubsampling::CS420);
+    }
+
+    #[test]
+    fn test_create_csparameters_text_quality_floor() {
+        let mut parameters = settings::ProfileData::new();
+        parameters.jpeg.text_quality_floor = 92;
+        let mut result = create_csparameters(&parameters, 100, 100, true);
+        assert_eq!(result.jpeg.quality, 92);
+
+        result = create_csparameters(&parameters, 100, 100, false);
+        assert_eq!(result.jpeg.quality, 80);
+
+        parameters.jpeg_quality = 95;
+        result = create_csparameters(&parameters, 100, 100, true);
+        assert_eq!(result.jpeg.quality, 95);
+    }
+
+    #[test]
+    fn test_create_csparameters_png() {
+        let mut parameters = settings::ProfileData::new();
+        parameters.png.optimization_level = 5;
+        let mut result = create_csparameters(&parameters, 100, 100, false);
+        assert_eq!(result.png.optimization_level, 5);
+
+        parameters.png.optimization_level = 12;
+        result = create_csparameters(&parameters, 100, 100, false);
+        assert_eq!(result.png.optimization_level, 6);
+    }
+
+    #[test]
+    fn test_resize_image() {
+        let img = DynamicImage::new_rgb8(800, 600);
+        let result = resize_image(img.clone(), 400, 0);
+        assert_eq!((result.width(), result.height()), (400, 300));
+        let result = resize_image(img.clone(), 0, 300);
+        assert_eq!((result.width(), result.height()), (400, 300));
+        let result = resize_image(img, 0, 0);
+        assert_eq!((result.width(), result.height()), (800, 600));
+    }
+
+    #[test]
+    fn test_contains_text() {
+        let text = image::GrayImage::from_fn(200, 200, |x, y| {
+            if y % 10 < 6 && x % 4 == 0 {
+                image::Luma([0])
+            } else {
+                image::Luma([255])
+            }
+        });
+        assert!(contains_text(&DynamicImage::ImageLuma8(text)));
+
+        let photo = image::GrayImage::from_fn(200, 200, |x, y| image::Luma([(x + y) as u8]));
+        assert!(!contains_text(&DynamicImage::ImageLuma8(photo)));
+    }
+
+    #[test]
+    fn test_get_page_out_path() {
+        let mut parameters = settings::ProfileData::new();
+        parameters.should_convert = true;
+        parameters.convert_extension = ImageType::PNG;
+        let result = get_page_out_path(&parameters, "test/scan.tiff", 2);
+        assert_eq!(result, "test/scan-2.min.png".to_string());
+
+        parameters.add_posfix = false;
+        let result = get_page_out_path(&parameters, "test/scan.tiff", 10);
+        assert_eq!(result, "test/scan-10.png".to_string());
+    }
+
+    #[test]
+    fn test_create_csparameters_tiff() {
+        let mut parameters = settings::ProfileData::new();
+        let mut result = create_csparameters(&parameters, 100, 100, false);
+        assert!(result.tiff.algorithm == TiffCompression::Deflate);
+        assert!(result.tiff.deflate_level == TiffDeflateLevel::Balanced);
+
+        parameters.tiff.compression = settings::TiffCompression::PackBits;
+        parameters.tiff.deflate_level = settings::TiffDeflateLevel::Best;
+        result = create_csparameters(&parameters, 100, 100, false);
+        assert!(result.tiff.algorithm == TiffCompression::Packbits);
+        assert!(result.tiff.deflate_level == TiffDeflateLevel::Best);
+
+        parameters.tiff.compression = settings::TiffCompression::None;
+        result = create_csparameters(&parameters, 100, 100, false);
+        assert!(result.tiff.algorithm == TiffCompression::Uncompressed);
+    }
+
+    #[test]
+    fn test_effective_quality() {
+        let mut parameters = settings::ProfileData::new();
+        parameters.jpeg.text_quality_floor = 90;
+        let csparams = create_csparameters(&parameters, 100, 100, true);
+        let quality = |p: &settings::ProfileData, t, lossless_jpeg| {
+            effective_quality(p, &csparams, t, lossless_jpeg)
+        };
+        assert_eq!(quality(&parameters, ImageType::JPEG, false), Some(90));
+        assert_eq!(quality(&parameters, ImageType::JPEG, true), None);
+        assert_eq!(quality(&parameters, ImageType::PNG, false), Some(80));
+        assert_eq!(quality(&parameters, ImageType::TIFF, false), None);
+        parameters.webp.mode = settings::WebpMode::Lossless;
+        assert_eq!(quality(&parameters, ImageType::WEBP, false), None);
+    }
+
+    #[test]
+    fn test_saves_enough() {
+        assert!(saves_enough(100, 95));
+        assert!(!saves_enough(100, 96));
+        let five_gb = 5 * 1024 * 1024 * 1024;
+        assert!(saves_enough(five_gb, five_gb / 2));
+        assert!(!saves_enough(five_gb, five_gb - 1));
+    }
+
+    #[test]
+    fn test_get_out_path_extensionless() {
+        let dir = std::env::temp_dir().join("alic_extensionless");
+        let _ = fs::create_dir_all(&dir);
+        fs::copy("test/test.jpg", dir.join("download")).unwrap();
+
+        let parameters = settings::ProfileData::new();
+        let out_path = get_out_path(&parameters, &dir.join("download").to_string_lossy());
+        assert!(out_path.ends_with("download.min.jpg"));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
     #[test]
     fn test_get_temp_path() {
         let result = get_temp_path(&"test/test.png".to_string());
         assert_eq!(result, "test/.test.png".to_string());
     }
+
+    #[test]
+    fn test_has_exif() {
+        assert!(has_exif("test/test.jpg"));
+        let out_path = std::env::temp_dir().join("alic-test-exif.jpg");
+        let out_path = out_path.to_string_lossy();
+        let options = settings::JpegOptions::default();
+        compress_jpeg_lossless("test/test.jpg", &out_path, &options).unwrap();
+        assert!(!has_exif(&out_path));
+        let _ = fs::remove_file(out_path.as_ref());
+    }
     // #[test]
     // fn test_process_image() {
     //     let parameters = Parameters {
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::io;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+// Everything that can go wrong while processing a file, with the path it happened to.
+// The message doesn't repeat the source, the chain is walked with `source()`.
+#[derive(Debug)]
+pub enum Error {
+    Io { path: String, source: io::Error },
+    Decode { path: String, source: BoxError },
+    Encode { path: String, source: BoxError },
+    Unsupported { path: String, reason: String },
+    LimitsExceeded { path: String, reason: String },
+    Verification { path: String, reason: String },
+    Cancelled { path: String },
+}
+
+impl Error {
+    pub fn io(path: &str, source: io::Error) -> Self {
+        Error::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn decode(path: &str, source: impl Into<BoxError>) -> Self {
+        Error::Decode {
+            path: path.to_string(),
+            source: source.into(),
+        }
+    }
+
+    pub fn encode(path: &str, source: impl Into<BoxError>) -> Self {
+        Error::Encode {
+            path: path.to_string(),
+            source: source.into(),
+        }
+    }
+
+    pub fn unsupported(path: &str, reason: impl Into<String>) -> Self {
+        Error::Unsupported {
+            path: path.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn limits(path: &str, reason: impl Into<String>) -> Self {
+        Error::LimitsExceeded {
+            path: path.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn verification(path: &str, reason: impl Into<String>) -> Self {
+        Error::Verification {
+            path: path.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    // Sorts an image error into the variant it belongs to
+    pub fn image(path: &str, err: image::ImageError) -> Self {
+        match err {
+            image::ImageError::IoError(e) => Error::io(path, e),
+            image::ImageError::Limits(e) => Error::limits(path, e.to_string()),
+            image::ImageError::Unsupported(e) => Error::unsupported(path, e.to_string()),
+            image::ImageError::Encoding(_) => Error::encode(path, err),
+            _ => Error::decode(path, err),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            Error::Io { path, .. }
+            | Error::Decode { path, .. }
+            | Error::Encode { path, .. }
+            | Error::Unsupported { path, .. }
+            | Error::LimitsExceeded { path, .. }
+            | Error::Verification { path, .. }
+            | Error::Cancelled { path } => path,
+        }
+    }
+
+    // The messages of every error below this one
+    pub fn causes(&self) -> Vec<String> {
+        let mut causes = vec![];
+        let mut source = std::error::Error::source(self);
+        while let Some(err) = source {
+            causes.push(err.to_string());
+            source = err.source();
+        }
+        causes
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, .. } => write!(f, "Cannot access {}", path),
+            Error::Decode { path, .. } => write!(f, "Cannot decode {}", path),
+            Error::Encode { path, .. } => write!(f, "Cannot encode {}", path),
+            Error::Unsupported { path, reason } => {
+                write!(f, "{} is not supported: {}", path, reason)
+            }
+            Error::LimitsExceeded { path, reason } => {
+                write!(f, "{} is over a limit: {}", path, reason)
+            }
+            Error::Verification { path, reason } => {
+                write!(f, "{} failed verification: {}", path, reason)
+            }
+            Error::Cancelled { path } => write!(f, "{} was cancelled", path),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Decode { source, .. } | Error::Encode { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_causes() {
+        let err = Error::io("a.png", io::Error::new(io::ErrorKind::NotFound, "gone"));
+        assert_eq!(err.to_string(), "Cannot access a.png");
+        assert_eq!(err.causes(), vec!["gone".to_string()]);
+        assert_eq!(err.path(), "a.png");
+
+        let err = Error::image(
+            "b.png",
+            image::ImageError::IoError(io::Error::other("broken pipe")),
+        );
+        assert!(matches!(err, Error::Io { .. }));
+        assert!(Error::unsupported("c.bmp", "BMP").causes().is_empty());
+    }
+}
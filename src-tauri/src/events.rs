@@ -1,9 +1,22 @@
+use crate::scan::{ScanError, ScanProgress};
 use tauri::Emitter;
 
 pub fn emit_add_file(app: &tauri::AppHandle, path: String) {
     app.emit("add-file", path).unwrap()
 }
 
+pub fn emit_add_files(app: &tauri::AppHandle, paths: Vec<String>) {
+    app.emit("add-files", paths).unwrap()
+}
+
+pub fn emit_scan_progress(app: &tauri::AppHandle, progress: ScanProgress) {
+    app.emit("scan-progress", progress).unwrap()
+}
+
+pub fn emit_scan_error(app: &tauri::AppHandle, error: ScanError) {
+    app.emit("scan-error", error).unwrap()
+}
+
 pub fn emit_clear_files(app: &tauri::AppHandle) {
     app.emit("clear-files", ()).unwrap()
 }
@@ -0,0 +1,92 @@
+use crate::settings::FileFilters;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Size, dimensions and modification time are separate criteria, an unset bound always passes.
+// Returns why the file was left out, or None when it should be compressed.
+pub fn skip_reason(
+    filters: &FileFilters,
+    size: u64,
+    width: u32,
+    height: u32,
+    modified: SystemTime,
+) -> Option<String> {
+    let kb = size / 1024;
+    let size_ok = (filters.min_size_kb == 0 || kb >= filters.min_size_kb as u64)
+        && (filters.max_size_kb == 0 || kb <= filters.max_size_kb as u64);
+    let dimensions_ok = (filters.min_width == 0 || width >= filters.min_width)
+        && (filters.max_width == 0 || width <= filters.max_width)
+        && (filters.min_height == 0 || height >= filters.min_height)
+        && (filters.max_height == 0 || height <= filters.max_height);
+    let modified = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let modified_ok = modified >= filters.modified_after;
+
+    let criteria = [
+        (size_ok, has_size_filter(filters), "size"),
+        (dimensions_ok, has_dimension_filter(filters), "dimensions"),
+        (modified_ok, filters.modified_after > 0, "modification time"),
+    ];
+    let active: Vec<_> = criteria.iter().filter(|(_, active, _)| *active).collect();
+    if active.is_empty() {
+        return None;
+    }
+    let failed: Vec<&str> = active
+        .iter()
+        .filter(|(ok, _, _)| !ok)
+        .map(|(_, _, name)| *name)
+        .collect();
+    let skip = match filters.match_any {
+        true => failed.len() == active.len(),
+        false => !failed.is_empty(),
+    };
+    skip.then(|| format!("Skipped by the profile's {} filter.", failed.join(" and ")))
+}
+
+fn has_size_filter(filters: &FileFilters) -> bool {
+    filters.min_size_kb > 0 || filters.max_size_kb > 0
+}
+
+fn has_dimension_filter(filters: &FileFilters) -> bool {
+    filters.min_width > 0
+        || filters.max_width > 0
+        || filters.min_height > 0
+        || filters.max_height > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_skip_reason() {
+        let now = SystemTime::now();
+        let mut filters = FileFilters::default();
+        assert_eq!(skip_reason(&filters, 10, 10, 10, now), None);
+
+        // Above 500 KB or wider than 2000 px
+        filters.min_size_kb = 500;
+        filters.min_width = 2000;
+        filters.match_any = true;
+        assert_eq!(skip_reason(&filters, 600 * 1024, 100, 100, now), None);
+        assert_eq!(skip_reason(&filters, 1024, 3000, 100, now), None);
+        assert!(skip_reason(&filters, 1024, 100, 100, now).is_some());
+
+        filters.match_any = false;
+        assert_eq!(skip_reason(&filters, 600 * 1024, 3000, 100, now), None);
+        assert_eq!(
+            skip_reason(&filters, 600 * 1024, 100, 100, now),
+            Some("Skipped by the profile's dimensions filter.".to_string())
+        );
+
+        let filters = FileFilters {
+            modified_after: now.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            ..FileFilters::default()
+        };
+        let old = now - Duration::from_secs(3600);
+        assert!(skip_reason(&filters, 10, 10, 10, old).is_some());
+        assert_eq!(skip_reason(&filters, 10, 10, 10, now), None);
+    }
+}
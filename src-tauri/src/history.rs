@@ -0,0 +1,267 @@
+use crate::compress::{CompressError, CompressErrorType};
+use crate::error::Error;
+use crate::settings::ProfileData;
+use sha2::{Digest, Sha256};
+use specta::Type;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+// Loaded on first use, every process_img call shares it
+static HISTORY: Mutex<Option<History>> = Mutex::new(None);
+
+// One compressed file, hashes are sha256 hex
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub input_hash: String,
+    pub output_hash: String,
+    pub profile_hash: String,
+    pub path: String,
+    pub out_path: String,
+    pub input_size: u64,
+    pub output_size: u64,
+    // Unix seconds
+    pub timestamp: u64,
+}
+
+// Append only, one JSON entry per line so a crash loses at most the last one
+struct History {
+    path: PathBuf,
+    entries: Vec<HistoryEntry>,
+    processed: HashMap<(String, String), usize>,
+    outputs: HashSet<String>,
+}
+
+impl History {
+    // Lines that don't parse are dropped
+    fn open(path: &Path) -> Self {
+        let mut history = History {
+            path: path.to_path_buf(),
+            entries: vec![],
+            processed: HashMap::new(),
+            outputs: HashSet::new(),
+        };
+        if let Ok(file) = File::open(path) {
+            BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str(&line).ok())
+                .for_each(|entry| history.insert(entry));
+        }
+        history
+    }
+
+    fn insert(&mut self, entry: HistoryEntry) {
+        self.processed.insert(
+            (entry.input_hash.clone(), entry.profile_hash.clone()),
+            self.entries.len(),
+        );
+        self.outputs.insert(entry.output_hash.clone());
+        self.entries.push(entry);
+    }
+
+    fn skip_reason(&self, input_hash: &str, profile_hash: &str) -> Option<String> {
+        if self.outputs.contains(input_hash) {
+            return Some("Image was already compressed by Alic.".to_string());
+        }
+        let key = (input_hash.to_string(), profile_hash.to_string());
+        self.processed.get(&key).map(|&i| {
+            format!(
+                "Image was already compressed with this profile to {}.",
+                self.entries[i].out_path
+            )
+        })
+    }
+
+    fn record(&mut self, entry: HistoryEntry) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        self.insert(entry);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.processed.clear();
+        self.outputs.clear();
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn with_history<T>(path: &Path, f: impl FnOnce(&mut History) -> T) -> T {
+    let mut history = HISTORY.lock().unwrap_or_else(|e| e.into_inner());
+    if history.as_ref().is_none_or(|h| h.path != path) {
+        *history = Some(History::open(path));
+    }
+    f(history.as_mut().unwrap())
+}
+
+// What compress_file needs to consult and update the history
+pub struct Tracker {
+    path: Option<PathBuf>,
+    skip_processed: bool,
+}
+
+impl Tracker {
+    pub fn new(app: &tauri::AppHandle, skip_processed: bool) -> Self {
+        Tracker {
+            path: history_path(app),
+            skip_processed,
+        }
+    }
+
+    pub fn skip_reason(&self, input_hash: &str, profile_hash: &str) -> Option<String> {
+        match &self.path {
+            Some(path) if self.skip_processed => {
+                with_history(path, |h| h.skip_reason(input_hash, profile_hash))
+            }
+            _ => None,
+        }
+    }
+
+    // A file that can't be recorded is still compressed, it just won't be skipped next time
+    pub fn record(&self, entry: HistoryEntry) {
+        if let Some(path) = &self.path {
+            if let Err(e) = with_history(path, |h| h.record(entry)) {
+                println!("Cannot write history: {}", e);
+            }
+        }
+    }
+}
+
+pub fn hash_file(path: &str) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// Only the settings that change the output, renaming or reordering a profile keeps its history
+pub fn profile_hash(profile: &ProfileData) -> String {
+    let mut value = serde_json::to_value(profile).unwrap_or_default();
+    if let Some(fields) = value.as_object_mut() {
+        for key in ["name", "id", "active", "scan", "filters"] {
+            fields.remove(key);
+        }
+    }
+    format!("{:x}", Sha256::digest(value.to_string()))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn history_path(app: &tauri::AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(HISTORY_FILE))
+}
+
+// Newest first, `path` narrows it to entries for that input or output
+#[tauri::command]
+#[specta::specta]
+pub async fn get_history(
+    app: tauri::AppHandle,
+    path: Option<String>,
+) -> Result<Vec<HistoryEntry>, CompressError> {
+    let history_path = history_path(&app).ok_or_else(no_app_data_dir)?;
+    Ok(with_history(&history_path, |h| {
+        h.entries
+            .iter()
+            .rev()
+            .filter(|e| {
+                path.as_ref()
+                    .is_none_or(|p| &e.path == p || &e.out_path == p)
+            })
+            .cloned()
+            .collect()
+    }))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn clear_history(app: tauri::AppHandle) -> Result<(), CompressError> {
+    let history_path = history_path(&app).ok_or_else(no_app_data_dir)?;
+    with_history(&history_path, |h| h.clear())
+        .map_err(|e| Error::io(&history_path.to_string_lossy(), e).into())
+}
+
+fn no_app_data_dir() -> CompressError {
+    CompressError::new(CompressErrorType::Io, "Cannot find the app data folder")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(input_hash: &str, output_hash: &str, profile_hash: &str) -> HistoryEntry {
+        HistoryEntry {
+            input_hash: input_hash.to_string(),
+            output_hash: output_hash.to_string(),
+            profile_hash: profile_hash.to_string(),
+            path: "/a.png".to_string(),
+            out_path: "/a.min.png".to_string(),
+            input_size: 100,
+            output_size: 50,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let path = std::env::temp_dir().join("alic-test-history.jsonl");
+        let _ = fs::remove_file(&path);
+        let mut history = History::open(&path);
+        history.record(entry("in", "out", "p1")).unwrap();
+        assert!(history.skip_reason("in", "p1").is_some());
+        assert!(history.skip_reason("out", "p2").is_some());
+        assert!(history.skip_reason("in", "p2").is_none());
+
+        // Survives a restart, broken lines are ignored
+        writeln!(OpenOptions::new().append(true).open(&path).unwrap(), "{{").unwrap();
+        let mut history = History::open(&path);
+        assert_eq!(history.entries, vec![entry("in", "out", "p1")]);
+        assert!(history.skip_reason("in", "p1").is_some());
+
+        history.clear().unwrap();
+        assert!(history.skip_reason("in", "p1").is_none());
+        assert!(History::open(&path).entries.is_empty());
+    }
+
+    #[test]
+    fn test_profile_hash() {
+        let profile = ProfileData::new();
+        let mut renamed = ProfileData::new_with_params(3, "Other".to_string());
+        renamed.scan.max_depth = 2;
+        assert_eq!(profile_hash(&profile), profile_hash(&renamed));
+        renamed.jpeg_quality = 50;
+        assert_ne!(profile_hash(&profile), profile_hash(&renamed));
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let path = std::env::temp_dir().join("alic-test-hash.txt");
+        fs::write(&path, "abc").unwrap();
+        assert_eq!(
+            hash_file(path.to_str().unwrap()).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
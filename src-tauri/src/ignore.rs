@@ -0,0 +1,177 @@
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".alicignore"];
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+// One line of a .gitignore, or one of the profile's glob patterns
+#[derive(Clone)]
+struct Rule {
+    pattern: Pattern,
+    negate: bool,
+    dir_only: bool,
+    // Patterns with a slash match the whole relative path, others just the name
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = Pattern::new(line.trim_start_matches('/')).ok()?;
+        Some(Rule {
+            pattern,
+            negate,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        match self.anchored {
+            true => self.pattern.matches_path_with(relative, MATCH_OPTIONS),
+            false => relative.file_name().is_some_and(|name| {
+                self.pattern
+                    .matches_with(&name.to_string_lossy(), MATCH_OPTIONS)
+            }),
+        }
+    }
+}
+
+// Patterns are checked in order and the last match wins, like git does
+#[derive(Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    // Invalid patterns are dropped
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Self {
+        RuleSet {
+            rules: patterns
+                .iter()
+                .filter_map(|p| Rule::parse(p.as_ref()))
+                .collect(),
+        }
+    }
+
+    // Settings come from a comma separated text field, so spaces around them don't count
+    pub fn from_globs(globs: &[String]) -> Self {
+        let globs: Vec<&str> = globs.iter().map(|g| g.trim()).collect();
+        Self::new(&globs)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Some(true) if matched, Some(false) if a negated pattern matched last
+    pub fn matched(&self, relative: &Path, is_dir: bool) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(relative, is_dir))
+            .map(|rule| !rule.negate)
+    }
+}
+
+// The ignore files of one directory, they apply to everything below it
+#[derive(Clone)]
+pub struct IgnoreFile {
+    base: PathBuf,
+    rules: RuleSet,
+}
+
+impl IgnoreFile {
+    pub fn load(dir: &Path) -> Option<Self> {
+        let lines: Vec<String> = IGNORE_FILES
+            .iter()
+            .filter_map(|name| fs::read_to_string(dir.join(name)).ok())
+            .flat_map(|text| text.lines().map(str::to_string).collect::<Vec<_>>())
+            .collect();
+        if lines.is_empty() {
+            return None;
+        }
+        Some(IgnoreFile {
+            base: dir.to_path_buf(),
+            rules: RuleSet::new(&lines),
+        })
+    }
+}
+
+// `ignores` goes from the scan root down, deeper files override shallower ones
+pub fn is_ignored(ignores: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    ignores
+        .iter()
+        .rev()
+        .find_map(|ignore| {
+            let relative = path.strip_prefix(&ignore.base).ok()?;
+            ignore.rules.matched(relative, is_dir)
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_set() {
+        let rules = RuleSet::new(&["# comment", "*.log", "build/", "/docs/*.png", "!keep.log"]);
+        assert_eq!(rules.matched(Path::new("a/b/c.log"), false), Some(true));
+        assert_eq!(rules.matched(Path::new("a/keep.log"), false), Some(false));
+        assert_eq!(rules.matched(Path::new("a/build"), true), Some(true));
+        assert_eq!(rules.matched(Path::new("a/build"), false), None);
+        assert_eq!(rules.matched(Path::new("docs/x.png"), false), Some(true));
+        assert_eq!(rules.matched(Path::new("a/docs/x.png"), false), None);
+
+        let rules = RuleSet::new(&["**/node_modules", "photos/**/*.jpg"]);
+        assert_eq!(rules.matched(Path::new("node_modules"), true), Some(true));
+        assert_eq!(rules.matched(Path::new("a/node_modules"), true), Some(true));
+        assert_eq!(
+            rules.matched(Path::new("photos/2024/x.jpg"), false),
+            Some(true)
+        );
+        assert_eq!(rules.matched(Path::new("other/x.jpg"), false), None);
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        let ignores = vec![
+            IgnoreFile {
+                base: PathBuf::from("/repo"),
+                rules: RuleSet::new(&["*.png"]),
+            },
+            IgnoreFile {
+                base: PathBuf::from("/repo/assets"),
+                rules: RuleSet::new(&["!*.png"]),
+            },
+        ];
+        assert!(is_ignored(&ignores, Path::new("/repo/a.png"), false));
+        assert!(!is_ignored(
+            &ignores,
+            Path::new("/repo/assets/a.png"),
+            false
+        ));
+        assert!(!is_ignored(&ignores, Path::new("/repo/a.jpg"), false));
+    }
+}
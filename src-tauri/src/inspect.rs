@@ -0,0 +1,344 @@
+use crate::compress::{guess_image_type, CompressError, CompressErrorType, ImageType};
+use crate::error::Error;
+use crate::settings::{self, DecodeLimits};
+use crate::tiff::{count_pages, probe_tiff, TiffHeader};
+use crate::webp::count_webp_frames;
+use ::tiff::ColorType;
+use image::ImageDecoder;
+use specta::Type;
+use std::fs;
+use std::io::BufReader;
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+pub struct ImageInfo {
+    pub size: u64,
+    // From the file's content, not its extension
+    pub image_type: ImageType,
+    pub width: u32,
+    pub height: u32,
+    // As stored in the file, e.g. "Rgb8" or "L1"
+    pub color_type: String,
+    pub bit_depth: u32,
+    pub has_alpha: bool,
+    // Animation frames, or pages for a TIFF
+    pub frames: u32,
+    pub icc_profile: Option<String>,
+    pub exif: Option<ExifSummary>,
+    // Rough guess from the bits per pixel, in percent
+    pub estimated_savings: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+pub struct ExifSummary {
+    pub camera: Option<String>,
+    pub date: Option<String>,
+    pub orientation: Option<u32>,
+    pub has_gps: bool,
+}
+
+pub fn inspect(path: &str, limits: &DecodeLimits) -> Result<ImageInfo, Error> {
+    let size = fs::metadata(path).map_err(|e| Error::io(path, e))?.len();
+    let image_type = guess_image_type(path)?;
+    let decoder = image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| Error::io(path, e))?
+        .into_decoder();
+    let pixels = match decoder {
+        Ok(decoder) => describe_pixels(decoder),
+        // Palette and bilevel pages are only read by our own TIFF reader
+        Err(_) if image_type == ImageType::TIFF => describe_tiff_pixels(&probe_tiff(path)?),
+        Err(err) => return Err(Error::image(path, err)),
+    };
+
+    let frames = match image_type {
+        ImageType::GIF => count_gif_frames(&fs::read(path).map_err(|e| Error::io(path, e))?),
+        ImageType::WEBP => count_webp_frames(&fs::read(path).map_err(|e| Error::io(path, e))?),
+        // Still worth showing the first page when a later one can't be read
+        ImageType::TIFF => count_pages(path, limits).unwrap_or(1) as u32,
+        _ => 1,
+    };
+    let (width, height) = (pixels.width, pixels.height);
+    Ok(ImageInfo {
+        size,
+        image_type,
+        width,
+        height,
+        color_type: pixels.color_type,
+        bit_depth: pixels.bit_depth,
+        has_alpha: pixels.has_alpha,
+        frames,
+        icc_profile: pixels.icc_profile,
+        exif: read_exif(path),
+        estimated_savings: estimate_savings(image_type, size, width, height, frames),
+    })
+}
+
+// The header fields of `ImageInfo`
+struct PixelInfo {
+    width: u32,
+    height: u32,
+    color_type: String,
+    bit_depth: u32,
+    has_alpha: bool,
+    icc_profile: Option<String>,
+}
+
+fn describe_pixels(mut decoder: impl ImageDecoder) -> PixelInfo {
+    let (width, height) = decoder.dimensions();
+    let original = decoder.original_color_type();
+    // A PNG with a tRNS chunk only gets its alpha channel once decoded
+    let has_alpha = decoder.color_type().has_alpha();
+    let icc_profile = decoder
+        .icc_profile()
+        .ok()
+        .flatten()
+        .and_then(|icc| icc_description(&icc));
+    PixelInfo {
+        width,
+        height,
+        color_type: format!("{:?}", original),
+        bit_depth: (original.bits_per_pixel() / original.channel_count().max(1) as u16) as u32,
+        has_alpha,
+        icc_profile,
+    }
+}
+
+// Named like the image crate's color types, which have no palette
+fn describe_tiff_pixels(header: &TiffHeader) -> PixelInfo {
+    let (name, bits) = match header.color_type {
+        ColorType::Gray(bits) => ("L", bits),
+        ColorType::GrayA(bits) => ("La", bits),
+        ColorType::RGB(bits) => ("Rgb", bits),
+        ColorType::RGBA(bits) => ("Rgba", bits),
+        ColorType::Palette(bits) => ("Palette", bits),
+        ColorType::CMYK(bits) => ("Cmyk", bits),
+        ColorType::YCbCr(bits) => ("YCbCr", bits),
+    };
+    PixelInfo {
+        width: header.width,
+        height: header.height,
+        color_type: format!("{}{}", name, bits),
+        bit_depth: bits as u32,
+        has_alpha: matches!(header.color_type, ColorType::GrayA(_) | ColorType::RGBA(_)),
+        icc_profile: None,
+    }
+}
+
+// What the default profile typically gets to, per pixel of a single frame
+fn typical_bits_per_pixel(image_type: ImageType) -> f64 {
+    match image_type {
+        ImageType::JPEG => 2.0,
+        ImageType::PNG => 4.0,
+        ImageType::WEBP => 1.5,
+        ImageType::GIF => 2.0,
+        ImageType::TIFF => 8.0,
+    }
+}
+
+fn estimate_savings(image_type: ImageType, size: u64, width: u32, height: u32, frames: u32) -> u32 {
+    let pixels = width as f64 * height as f64 * frames.max(1) as f64;
+    if pixels == 0.0 {
+        return 0;
+    }
+    let bits_per_pixel = size as f64 * 8.0 / pixels;
+    let savings = 1.0 - typical_bits_per_pixel(image_type) / bits_per_pixel;
+    (savings.clamp(0.0, 0.95) * 100.0).round() as u32
+}
+
+// The "desc" tag, in either the ICC v2 or v4 layout
+fn icc_description(icc: &[u8]) -> Option<String> {
+    let u32_at = |offset: usize| -> Option<usize> {
+        let bytes = icc.get(offset..offset + 4)?;
+        Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize)
+    };
+    let tag_count = u32_at(128)?;
+    let (offset, size) = (0..tag_count.min(1024)).find_map(|i| {
+        let entry = 132 + i * 12;
+        match icc.get(entry..entry + 4)? == b"desc" {
+            true => Some((u32_at(entry + 4)?, u32_at(entry + 8)?)),
+            false => None,
+        }
+    })?;
+    let tag = icc.get(offset..offset.checked_add(size)?)?;
+    let text = match tag.get(0..4)? {
+        // textDescriptionType: a length, then ASCII with a trailing NUL
+        b"desc" => {
+            let length = u32::from_be_bytes(tag.get(8..12)?.try_into().ok()?) as usize;
+            String::from_utf8_lossy(tag.get(12..12 + length)?).to_string()
+        }
+        // multiLocalizedUnicodeType: the first record is used, UTF-16BE
+        b"mluc" => {
+            let length = u32::from_be_bytes(tag.get(20..24)?.try_into().ok()?) as usize;
+            let start = u32::from_be_bytes(tag.get(24..28)?.try_into().ok()?) as usize;
+            let units: Vec<u16> = tag
+                .get(start..start + length)?
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None,
+    };
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn read_exif(path: &str) -> Option<ExifSummary> {
+    let file = fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let text = |tag: exif::Tag| -> Option<String> {
+        match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+            exif::Value::Ascii(values) => {
+                let text = String::from_utf8_lossy(values.first()?);
+                let text = text.trim_end_matches('\0').trim();
+                (!text.is_empty()).then(|| text.to_string())
+            }
+            _ => None,
+        }
+    };
+    // Most models already start with the make, "Canon Canon EOS R5" reads badly
+    let camera = match (text(exif::Tag::Make), text(exif::Tag::Model)) {
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => model.or(make),
+    };
+    let summary = ExifSummary {
+        camera,
+        date: text(exif::Tag::DateTimeOriginal).or_else(|| text(exif::Tag::DateTime)),
+        orientation: exif
+            .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0)),
+        has_gps: exif
+            .fields()
+            .any(|field| field.tag.context() == exif::Context::Gps),
+    };
+    Some(summary)
+}
+
+// Walks the blocks without decoding any frames
+fn count_gif_frames(data: &[u8]) -> u32 {
+    let color_table = |packed: u8| match packed & 0x80 {
+        0 => 0,
+        _ => 3 << ((packed & 0x07) + 1),
+    };
+    // Skips data sub-blocks, returns the position after the terminator
+    let sub_blocks = |mut pos: usize| -> Option<usize> {
+        loop {
+            let length = *data.get(pos)? as usize;
+            pos += 1 + length;
+            if length == 0 {
+                return Some(pos);
+            }
+        }
+    };
+    let Some(&packed) = data.get(10) else {
+        return 0;
+    };
+    let mut pos = 13 + color_table(packed);
+    let mut frames = 0;
+    loop {
+        let next = match data.get(pos) {
+            Some(0x21) => sub_blocks(pos + 2),
+            Some(0x2C) => {
+                frames += 1;
+                data.get(pos + 9)
+                    .and_then(|&packed| sub_blocks(pos + 11 + color_table(packed)))
+            }
+            _ => None,
+        };
+        match next {
+            Some(next) => pos = next,
+            // The trailer, or a truncated file
+            None => return frames,
+        }
+    }
+}
+
+// get_file_info with everything the header and metadata can tell
+#[tauri::command]
+#[specta::specta]
+pub async fn inspect_image(
+    app: tauri::AppHandle,
+    path: String,
+) -> Result<ImageInfo, CompressError> {
+    let limits = settings::get_settings_data(&app).limits;
+    tauri::async_runtime::spawn_blocking(move || inspect(&path, &limits))
+        .await
+        .map_err(|e| CompressError::new(CompressErrorType::Unknown, e.to_string()))?
+        .map_err(CompressError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::{Frame, RgbaImage};
+
+    #[test]
+    fn test_inspect() {
+        let info = inspect("test/test.jpg", &DecodeLimits::default()).unwrap();
+        assert_eq!(info.image_type, ImageType::JPEG);
+        assert!(info.width > 0 && info.height > 0);
+        assert_eq!(info.color_type, "Rgb8");
+        assert_eq!(info.bit_depth, 8);
+        assert!(!info.has_alpha);
+        assert_eq!(info.frames, 1);
+        assert!(info.estimated_savings <= 95);
+    }
+
+    #[test]
+    fn test_inspect_palette_tiff() {
+        let colors = [0, 0xFFFF, 0, 0, 0, 0, 0xFFFF, 0, 0, 0, 0, 0xFFFF];
+        let data = crate::tiff::tests::tiff_bytes(4, 2, 2, 3, &[0b0001_1011, 0b1110_0100], &colors);
+        let path = std::env::temp_dir().join("alic_inspect_palette.tiff");
+        fs::write(&path, data).unwrap();
+        let info = inspect(path.to_str().unwrap(), &DecodeLimits::default()).unwrap();
+        assert_eq!(info.image_type, ImageType::TIFF);
+        assert_eq!((info.width, info.height), (4, 2));
+        assert_eq!(info.color_type, "Palette2");
+        assert_eq!(info.bit_depth, 2);
+        assert_eq!(info.frames, 1);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_count_gif_frames() {
+        let mut data = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            for i in 0..3 {
+                let frame = RgbaImage::from_pixel(4, 4, image::Rgba([i * 80, 0, 0, 255]));
+                encoder.encode_frame(Frame::new(frame)).unwrap();
+            }
+        }
+        assert_eq!(count_gif_frames(&data), 3);
+        assert!(count_gif_frames(&data[..data.len() / 2]) < 3);
+    }
+
+    #[test]
+    fn test_icc_description() {
+        let mut icc = vec![0u8; 128];
+        icc.extend(1u32.to_be_bytes());
+        icc.extend(b"desc");
+        icc.extend(144u32.to_be_bytes());
+        icc.extend(22u32.to_be_bytes());
+        icc.extend(b"desc\0\0\0\0");
+        icc.extend(10u32.to_be_bytes());
+        icc.extend(b"Display P3");
+        assert_eq!(icc_description(&icc), Some("Display P3".to_string()));
+        assert_eq!(icc_description(&icc[..140]), None);
+    }
+
+    #[test]
+    fn test_estimate_savings() {
+        // 1000x1000 at 8 bits per pixel, against 2 for a JPEG
+        assert_eq!(
+            estimate_savings(ImageType::JPEG, 1_000_000, 1000, 1000, 1),
+            75
+        );
+        assert_eq!(estimate_savings(ImageType::JPEG, 100_000, 1000, 1000, 1), 0);
+        assert_eq!(estimate_savings(ImageType::PNG, 100, 0, 0, 1), 0);
+    }
+}
@@ -0,0 +1,422 @@
+use crate::error::Error;
+use crate::settings::{JpegOptions, JpegRotation};
+use mozjpeg_sys::*;
+use std::ffi::CStr;
+use std::fs;
+use std::io::Cursor;
+use std::mem;
+use std::os::raw::{c_uint, c_ulong, c_void};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::ptr;
+
+const APP1: u8 = 0xE1;
+// libjpeg's JMSG_LENGTH_MAX, mozjpeg-sys declares a shorter buffer
+const MESSAGE_LENGTH: usize = 200;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const ORIENTATION_TAG: u16 = 0x0112;
+
+// Works on the DCT coefficients like jpegtran, the pixels are never decoded.
+// Auto rotation applies the EXIF orientation and then resets it. Metadata is
+// stripped unless kept in the options, but an orientation that wasn't applied
+// is always written back so the image still shows the right way up.
+pub fn compress_jpeg_lossless(
+    path: &str,
+    out_path: &str,
+    options: &JpegOptions,
+) -> Result<(), Error> {
+    let out_file = compress_jpeg_lossless_in_memory(path, options)?;
+    fs::write(out_path, out_file).map_err(|e| Error::io(out_path, e))
+}
+
+pub fn compress_jpeg_lossless_in_memory(
+    path: &str,
+    options: &JpegOptions,
+) -> Result<Vec<u8>, Error> {
+    let in_file = fs::read(path).map_err(|e| Error::io(path, e))?;
+    let orientation = get_orientation(&in_file);
+    let transform = match options.rotation {
+        JpegRotation::None => JXFORM_CODE_JXFORM_NONE,
+        JpegRotation::Auto => orientation_transform(orientation),
+        JpegRotation::Rotate90 => JXFORM_CODE_JXFORM_ROT_90,
+        JpegRotation::Rotate180 => JXFORM_CODE_JXFORM_ROT_180,
+        JpegRotation::Rotate270 => JXFORM_CODE_JXFORM_ROT_270,
+    };
+    let job = Job {
+        transform,
+        auto_rotate: options.rotation == JpegRotation::Auto,
+        orientation,
+        progressive: options.progressive,
+        keep_metadata: options.keep_metadata,
+    };
+    let mut error = JpegError::default();
+    catch_unwind(AssertUnwindSafe(|| unsafe {
+        transcode(path, &in_file, &job, &mut error)
+    }))
+    .unwrap_or_else(|_| {
+        Err(Error::encode(
+            path,
+            format!("Internal JPEG error: {}", error.message),
+        ))
+    })
+}
+
+fn get_orientation(data: &[u8]) -> u32 {
+    let exif_data = match exif::Reader::new().read_from_container(&mut Cursor::new(data)) {
+        Ok(exif_data) => exif_data,
+        Err(_) => return 1,
+    };
+    match exif_data.get_field(exif::Tag::Orientation, exif::In::PRIMARY) {
+        Some(field) => field.value.get_uint(0).unwrap_or(1),
+        None => 1,
+    }
+}
+
+fn orientation_transform(orientation: u32) -> JXFORM_CODE {
+    match orientation {
+        2 => JXFORM_CODE_JXFORM_FLIP_H,
+        3 => JXFORM_CODE_JXFORM_ROT_180,
+        4 => JXFORM_CODE_JXFORM_FLIP_V,
+        5 => JXFORM_CODE_JXFORM_TRANSPOSE,
+        6 => JXFORM_CODE_JXFORM_ROT_90,
+        7 => JXFORM_CODE_JXFORM_TRANSVERSE,
+        8 => JXFORM_CODE_JXFORM_ROT_270,
+        _ => JXFORM_CODE_JXFORM_NONE,
+    }
+}
+
+// An APP1 segment with nothing but the orientation, for stripped output
+fn orientation_exif(orientation: u16) -> Vec<u8> {
+    let mut exif = EXIF_HEADER.to_vec();
+    exif.extend(b"MM\0\x2A");
+    exif.extend(8u32.to_be_bytes());
+    exif.extend(1u16.to_be_bytes());
+    exif.extend(ORIENTATION_TAG.to_be_bytes());
+    // One SHORT, left-justified in the value field
+    exif.extend(3u16.to_be_bytes());
+    exif.extend(1u32.to_be_bytes());
+    exif.extend(orientation.to_be_bytes());
+    exif.extend([0, 0]);
+    // No next IFD
+    exif.extend(0u32.to_be_bytes());
+    exif
+}
+
+// Sets the orientation in an APP1 EXIF segment to 1, in place. None if it has none.
+fn reset_orientation(app1: &mut [u8]) -> Option<()> {
+    if !app1.starts_with(EXIF_HEADER) {
+        return None;
+    }
+    let tiff = &mut app1[EXIF_HEADER.len()..];
+    let big_endian = match tiff.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let read = |data: &[u8], pos: usize, len: usize| -> Option<u32> {
+        let bytes = data.get(pos..pos + len)?;
+        Some(match big_endian {
+            true => bytes.iter().fold(0, |v, &b| v << 8 | b as u32),
+            false => bytes.iter().rev().fold(0, |v, &b| v << 8 | b as u32),
+        })
+    };
+    let ifd = read(tiff, 4, 4)? as usize;
+    let entries = read(tiff, ifd, 2)? as usize;
+    let entry = (0..entries)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| read(tiff, entry, 2) == Some(ORIENTATION_TAG as u32))?;
+    let one = match big_endian {
+        true => 1u16.to_be_bytes(),
+        false => 1u16.to_le_bytes(),
+    };
+    tiff.get_mut(entry + 8..entry + 10)?.copy_from_slice(&one);
+    Some(())
+}
+
+struct Job {
+    transform: JXFORM_CODE,
+    auto_rotate: bool,
+    orientation: u32,
+    progressive: bool,
+    keep_metadata: bool,
+}
+
+// Filled in by error_handler through client_data, one per call so jobs
+// running in parallel can't see each other's errors
+#[derive(Default)]
+struct JpegError {
+    message: String,
+}
+
+// Owns everything libjpeg allocates, so an error unwinding out of transcode
+// still destroys both structs and frees the output buffer. Boxed, libjpeg
+// keeps pointers into it.
+struct Transcoder {
+    src_info: jpeg_decompress_struct,
+    src_err: jpeg_error_mgr,
+    dst_info: jpeg_compress_struct,
+    dst_err: jpeg_error_mgr,
+    buf: *mut u8,
+    buf_size: c_ulong,
+}
+
+impl Transcoder {
+    unsafe fn new(error: &mut JpegError) -> Box<Self> {
+        let mut t: Box<Transcoder> = Box::new(mem::zeroed());
+        let client_data = error as *mut JpegError as *mut c_void;
+        t.src_info.common.err = jpeg_std_error(&mut t.src_err);
+        t.src_err.error_exit = Some(error_handler);
+        t.src_err.output_message = Some(error_message_handler);
+        t.src_info.common.client_data = client_data;
+        t.dst_info.common.err = jpeg_std_error(&mut t.dst_err);
+        t.dst_err.error_exit = Some(error_handler);
+        t.dst_err.output_message = Some(error_message_handler);
+        t.dst_info.common.client_data = client_data;
+        jpeg_create_decompress(&mut t.src_info);
+        jpeg_create_compress(&mut t.dst_info);
+        t
+    }
+}
+
+impl Drop for Transcoder {
+    fn drop(&mut self) {
+        unsafe {
+            jpeg_destroy_compress(&mut self.dst_info);
+            jpeg_destroy_decompress(&mut self.src_info);
+            if !self.buf.is_null() {
+                libc::free(self.buf as *mut c_void);
+            }
+        }
+    }
+}
+
+unsafe fn transcode(
+    path: &str,
+    in_file: &[u8],
+    job: &Job,
+    error: &mut JpegError,
+) -> Result<Vec<u8>, Error> {
+    let mut transcoder = Transcoder::new(error);
+    let t = &mut *transcoder;
+    jpeg_mem_src(&mut t.src_info, in_file.as_ptr(), in_file.len() as _);
+    let copy = match job.keep_metadata {
+        true => JCOPY_OPTION_JCOPYOPT_ALL,
+        false => JCOPY_OPTION_JCOPYOPT_ICC,
+    };
+    jcopy_markers_setup(&mut t.src_info, copy);
+    jpeg_read_header(&mut t.src_info, true as boolean);
+
+    // Refuse to drop the partial edge blocks a rotation can't move. Such an image
+    // keeps its EXIF orientation when auto rotating, an explicit rotation fails.
+    let mut transform_info: jpeg_transform_info = mem::zeroed();
+    transform_info.transform = job.transform;
+    transform_info.perfect = true as boolean;
+    if jtransform_request_workspace(&mut t.src_info, &mut transform_info) == 0 {
+        if !job.auto_rotate {
+            return Err(Error::unsupported(
+                path,
+                "Can't be rotated losslessly, its size isn't a multiple of the JPEG block size",
+            ));
+        }
+        transform_info = mem::zeroed();
+        transform_info.transform = JXFORM_CODE_JXFORM_NONE;
+        jtransform_request_workspace(&mut t.src_info, &mut transform_info);
+    }
+    let rotated = job.auto_rotate && transform_info.transform != JXFORM_CODE_JXFORM_NONE;
+    if rotated && job.keep_metadata {
+        let mut marker = t.src_info.marker_list;
+        while !marker.is_null() {
+            if (*marker).marker == APP1 && !(*marker).data.is_null() {
+                let data =
+                    std::slice::from_raw_parts_mut((*marker).data, (*marker).data_length as usize);
+                reset_orientation(data);
+            }
+            marker = (*marker).next;
+        }
+    }
+
+    let src_coef_arrays = jpeg_read_coefficients(&mut t.src_info);
+    jpeg_copy_critical_parameters(&t.src_info, &mut t.dst_info);
+    let dst_coef_arrays = jtransform_adjust_parameters(
+        &mut t.src_info,
+        &mut t.dst_info,
+        src_coef_arrays,
+        &mut transform_info,
+    );
+
+    t.dst_info.optimize_coding = true as boolean;
+    if !job.progressive {
+        t.dst_info.scan_info = ptr::null();
+    }
+    jpeg_mem_dest(&mut t.dst_info, &mut t.buf, &mut t.buf_size);
+    jpeg_write_coefficients(&mut t.dst_info, dst_coef_arrays);
+    jcopy_markers_execute(&mut t.src_info, &mut t.dst_info, copy);
+    if !job.keep_metadata && !rotated && job.orientation != 1 {
+        let exif = orientation_exif(job.orientation as u16);
+        jpeg_write_marker(
+            &mut t.dst_info,
+            APP1 as i32,
+            exif.as_ptr(),
+            exif.len() as c_uint,
+        );
+    }
+    jtransform_execute_transform(
+        &mut t.src_info,
+        &mut t.dst_info,
+        src_coef_arrays,
+        &mut transform_info,
+    );
+
+    jpeg_finish_compress(&mut t.dst_info);
+    jpeg_finish_decompress(&mut t.src_info);
+    Ok(std::slice::from_raw_parts(t.buf, t.buf_size as usize).to_vec())
+}
+
+unsafe extern "C-unwind" fn error_handler(cinfo: &mut jpeg_common_struct) {
+    let mut buffer = [0u8; MESSAGE_LENGTH];
+    if let Some(format_message) = (*cinfo.err).format_message {
+        // It writes to the buffer, which the declared &[u8; 80] hides
+        let format_message: unsafe extern "C-unwind" fn(&mut jpeg_common_struct, *mut u8) =
+            mem::transmute(format_message);
+        format_message(cinfo, buffer.as_mut_ptr());
+    }
+    let error = &mut *(cinfo.client_data as *mut JpegError);
+    error.message = CStr::from_bytes_until_nul(&buffer)
+        .map(|message| message.to_string_lossy().to_string())
+        .unwrap_or_else(|_| (*cinfo.err).msg_code.to_string());
+    // Unwinds back to catch_unwind without the panic hook printing anything
+    resume_unwind(Box::new(()));
+}
+
+unsafe extern "C-unwind" fn error_message_handler(_cinfo: &mut jpeg_common_struct) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossless_rotation() {
+        let dir = std::env::temp_dir();
+        let input = dir.join("alic_lossless.jpg").to_string_lossy().to_string();
+        let output = dir
+            .join("alic_lossless.out.jpg")
+            .to_string_lossy()
+            .to_string();
+        let image = image::RgbImage::from_fn(32, 16, |x, y| {
+            image::Rgb([(x * 8) as u8, (y * 16) as u8, 64])
+        });
+        image.save(&input).unwrap();
+
+        let mut options = JpegOptions {
+            rotation: JpegRotation::None,
+            ..JpegOptions::default()
+        };
+        compress_jpeg_lossless(&input, &output, &options).unwrap();
+        let original = image::open(&input).unwrap().to_rgb8();
+        let optimized = image::open(&output).unwrap().to_rgb8();
+        assert_eq!(original, optimized);
+
+        options.rotation = JpegRotation::Rotate90;
+        compress_jpeg_lossless(&input, &output, &options).unwrap();
+        let rotated = image::open(&output).unwrap();
+        assert_eq!((rotated.width(), rotated.height()), (16, 32));
+
+        let _ = fs::remove_file(input);
+        let _ = fs::remove_file(output);
+    }
+
+    #[test]
+    fn test_rotation_not_lossless() {
+        let dir = std::env::temp_dir();
+        let input = dir.join("alic_partial.jpg").to_string_lossy().to_string();
+        let output = dir
+            .join("alic_partial.out.jpg")
+            .to_string_lossy()
+            .to_string();
+        // The right and bottom edges are partial blocks, which a rotation would have to drop
+        let image = image::RgbImage::from_fn(20, 20, |x, y| {
+            image::Rgb([(x * 8) as u8, (y * 16) as u8, 64])
+        });
+        image.save(&input).unwrap();
+        let _ = fs::remove_file(&output);
+
+        let options = JpegOptions {
+            rotation: JpegRotation::Rotate90,
+            ..JpegOptions::default()
+        };
+        let err = compress_jpeg_lossless(&input, &output, &options).unwrap_err();
+        assert!(matches!(err, Error::Unsupported { .. }));
+        assert!(!std::path::Path::new(&output).exists());
+
+        let _ = fs::remove_file(input);
+    }
+
+    // Stored `width` x `height`, shown rotated by the EXIF orientation
+    fn jpeg_with_orientation(width: u32, height: u32, orientation: u16) -> Vec<u8> {
+        let image = image::RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 8) as u8, (y * 8) as u8, 64])
+        });
+        let mut jpeg = vec![];
+        image::DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let exif = orientation_exif(orientation);
+        let mut segment = vec![0xFF, APP1];
+        segment.extend((exif.len() as u16 + 2).to_be_bytes());
+        segment.extend(exif);
+        jpeg.splice(2..2, segment);
+        jpeg
+    }
+
+    #[test]
+    fn test_lossless_orientation() {
+        let path = std::env::temp_dir().join("alic_orientation.jpg");
+        let path = path.to_string_lossy().to_string();
+        let run = |jpeg: &[u8], options: &JpegOptions| {
+            fs::write(&path, jpeg).unwrap();
+            let out = compress_jpeg_lossless_in_memory(&path, options).unwrap();
+            let image = image::load_from_memory(&out).unwrap();
+            let has_exif = exif::Reader::new()
+                .read_from_container(&mut Cursor::new(&out))
+                .is_ok();
+            (
+                (image.width(), image.height()),
+                get_orientation(&out),
+                has_exif,
+            )
+        };
+        let mut options = JpegOptions::default();
+        let rotated = jpeg_with_orientation(32, 16, 6);
+        assert_eq!(run(&rotated, &options), ((16, 32), 1, false));
+        options.keep_metadata = true;
+        assert_eq!(run(&rotated, &options), ((16, 32), 1, true));
+        options.keep_metadata = false;
+        options.rotation = JpegRotation::None;
+        assert_eq!(run(&rotated, &options), ((32, 16), 6, true));
+
+        // Partial edge blocks, copied without rotating
+        options.rotation = JpegRotation::Auto;
+        let uneven = jpeg_with_orientation(30, 20, 6);
+        assert_eq!(run(&uneven, &options), ((30, 20), 6, true));
+
+        fs::write(&path, b"\xFF\xD8 not a JPEG").unwrap();
+        let err = compress_jpeg_lossless_in_memory(&path, &options).unwrap_err();
+        assert!(err.causes()[0].starts_with("Internal JPEG error: "));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_reset_orientation() {
+        let mut exif = orientation_exif(8);
+        assert_eq!(reset_orientation(&mut exif), Some(()));
+        assert_eq!(exif, orientation_exif(1));
+        assert_eq!(reset_orientation(&mut exif[..20]), None);
+        assert_eq!(reset_orientation(&mut b"Exif\0\0II*\0".to_vec()), None);
+    }
+
+    #[test]
+    fn test_orientation_transform() {
+        assert_eq!(orientation_transform(1), JXFORM_CODE_JXFORM_NONE);
+        assert_eq!(orientation_transform(6), JXFORM_CODE_JXFORM_ROT_90);
+        assert_eq!(orientation_transform(8), JXFORM_CODE_JXFORM_ROT_270);
+    }
+}
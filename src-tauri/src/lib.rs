@@ -1,7 +1,25 @@
+mod backup;
 mod compress;
+mod error;
 mod events;
+mod filter;
+mod history;
+mod ignore;
+mod inspect;
+mod jpeg;
+mod limits;
 mod macos;
+mod metrics;
+mod png;
+mod preview;
+mod queue;
+mod reduce;
+mod report;
+mod scan;
 mod settings;
+mod tiff;
+mod verify;
+mod webp;
 
 use std::hash::{DefaultHasher, Hash, Hasher};
 
@@ -84,27 +102,53 @@ fn save_clipboard_image(app: &tauri::AppHandle) {
     emit_add_file(&app, path);
 }
 
+// `alic report`, run from main before any window opens
+pub use report::run_cli as run_report_cli;
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let builder = Builder::<tauri::Wry>::new().commands(collect_commands![
-        open_settings_window,
-        compress::process_img,
-        compress::get_file_info,
-        compress::get_all_images,
-        settings::get_settings,
-        settings::save_settings,
-        settings::reset_settings,
-        settings::reset_profile,
-        settings::delete_profile,
-        settings::add_profile,
-        macos::open_finder_at_path,
-        macos::get_cpu_count,
-    ]);
+    let builder = Builder::<tauri::Wry>::new()
+        .commands(collect_commands![
+            open_settings_window,
+            compress::process_img,
+            compress::get_file_info,
+            compress::wait_for_job,
+            scan::get_all_images,
+            scan::cancel_scan,
+            history::get_history,
+            history::clear_history,
+            backup::get_journal,
+            backup::new_batch,
+            backup::undo_file,
+            backup::undo_batch,
+            backup::prune_backups,
+            queue::get_queue,
+            queue::queue_files,
+            queue::unqueue_files,
+            queue::clear_queue,
+            report::export_report,
+            metrics::compare_images,
+            preview::preview_image,
+            inspect::inspect_image,
+            settings::get_settings,
+            settings::save_settings,
+            settings::reset_settings,
+            settings::reset_profile,
+            settings::delete_profile,
+            settings::add_profile,
+            macos::open_finder_at_path,
+            macos::get_cpu_count,
+        ])
+        // Event payloads, emitted without a command to pull them in
+        .typ::<scan::ScanProgress>()
+        .typ::<scan::ScanError>();
 
     #[cfg(debug_assertions)] // <- Only export on non-release builds
     builder
         .export(
-            specta_typescript::Typescript::default(),
+            // File sizes are u64, they stay exact as JS numbers up to 8 PB
+            specta_typescript::Typescript::default()
+                .bigint(specta_typescript::BigIntExportBehavior::Number),
             "../src/bindings.ts",
         )
         .expect("Failed to export typescript bindings");
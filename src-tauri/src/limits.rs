@@ -0,0 +1,92 @@
+use crate::error::Error;
+use crate::settings::DecodeLimits;
+
+const MB: u64 = 1024 * 1024;
+
+pub fn check_file_size(path: &str, limits: &DecodeLimits, size: u64) -> Result<(), Error> {
+    if limits.max_file_mb > 0 && size > limits.max_file_mb as u64 * MB {
+        return Err(Error::limits(
+            path,
+            format!("File is larger than the {} MB limit", limits.max_file_mb),
+        ));
+    }
+    Ok(())
+}
+
+// Checked against the header, before anything is decoded
+pub fn check_dimensions(
+    path: &str,
+    limits: &DecodeLimits,
+    width: u32,
+    height: u32,
+    bytes_per_pixel: u64,
+) -> Result<(), Error> {
+    if (limits.max_width > 0 && width > limits.max_width)
+        || (limits.max_height > 0 && height > limits.max_height)
+    {
+        return Err(Error::limits(
+            path,
+            format!("Image is {}x{}, over the dimension limit", width, height),
+        ));
+    }
+    let bytes = width as u64 * height as u64 * bytes_per_pixel;
+    if limits.max_alloc_mb > 0 && bytes > limits.max_alloc_mb as u64 * MB {
+        return Err(Error::limits(
+            path,
+            format!(
+                "Decoding needs {} MB, more than the {} MB limit",
+                bytes.div_ceil(MB),
+                limits.max_alloc_mb
+            ),
+        ));
+    }
+    Ok(())
+}
+
+pub fn image_limits(limits: &DecodeLimits) -> image::Limits {
+    let mut image_limits = image::Limits::no_limits();
+    image_limits.max_image_width = (limits.max_width > 0).then_some(limits.max_width);
+    image_limits.max_image_height = (limits.max_height > 0).then_some(limits.max_height);
+    image_limits.max_alloc = (limits.max_alloc_mb > 0).then_some(limits.max_alloc_mb as u64 * MB);
+    image_limits
+}
+
+pub fn tiff_limits(limits: &DecodeLimits) -> ::tiff::decoder::Limits {
+    let mut tiff_limits = ::tiff::decoder::Limits::unlimited();
+    if limits.max_alloc_mb > 0 {
+        let bytes = (limits.max_alloc_mb as u64 * MB).min(usize::MAX as u64) as usize;
+        tiff_limits.decoding_buffer_size = bytes;
+        tiff_limits.intermediate_buffer_size = bytes;
+    }
+    tiff_limits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_dimensions() {
+        let limits = DecodeLimits {
+            max_width: 1000,
+            max_height: 1000,
+            max_alloc_mb: 1,
+            max_file_mb: 1,
+        };
+        assert!(check_dimensions("a.png", &limits, 500, 500, 4).is_ok());
+        assert!(check_dimensions("a.png", &limits, 60000, 60000, 4).is_err());
+        // Fits the dimensions but not the allocation
+        assert!(check_dimensions("a.png", &limits, 1000, 1000, 4).is_err());
+        assert!(check_file_size("a.png", &limits, 1024).is_ok());
+        assert!(check_file_size("a.png", &limits, 2 * MB).is_err());
+
+        let unlimited = DecodeLimits {
+            max_width: 0,
+            max_height: 0,
+            max_alloc_mb: 0,
+            max_file_mb: 0,
+        };
+        assert!(check_dimensions("a.png", &unlimited, 60000, 60000, 4).is_ok());
+        assert!(check_file_size("a.png", &unlimited, u64::MAX).is_ok());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::compress::{read_image, CompressError, CompressErrorType};
+use crate::error::Error;
+use crate::limits::image_limits;
+use crate::settings::{self, DecodeLimits};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, Rgb, RgbImage, RgbaImage};
+use specta::Type;
+
+// SSIM is computed on 8x8 luma windows, moved 4 pixels at a time
+const SSIM_WINDOW: u32 = 8;
+const SSIM_STEP: u32 = 4;
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+pub struct QualityMetrics {
+    // In dB, None when the images are identical
+    pub psnr: Option<f64>,
+    // 1 is identical
+    pub ssim: f64,
+    // Largest difference in any channel, 0-255
+    pub max_error: u32,
+}
+
+// Transparent pixels can hold any colour, so everything is compared over black
+fn premultiplied(img: &DynamicImage) -> RgbaImage {
+    let mut img = img.to_rgba8();
+    for pixel in img.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for c in 0..3 {
+            pixel[c] = (pixel[c] as u32 * alpha / 255) as u8;
+        }
+    }
+    img
+}
+
+fn luma(pixel: &image::Rgba<u8>) -> f64 {
+    0.299 * pixel[0] as f64 + 0.587 * pixel[1] as f64 + 0.114 * pixel[2] as f64
+}
+
+fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let (width, height) = a.dimensions();
+    let window = SSIM_WINDOW.min(width).min(height);
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y in (0..=height - window).step_by(SSIM_STEP as usize) {
+        for x in (0..=width - window).step_by(SSIM_STEP as usize) {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for wy in y..y + window {
+                for wx in x..x + window {
+                    let la = luma(a.get_pixel(wx, wy));
+                    let lb = luma(b.get_pixel(wx, wy));
+                    sum_a += la;
+                    sum_b += lb;
+                    sum_aa += la * la;
+                    sum_bb += lb * lb;
+                    sum_ab += la * lb;
+                }
+            }
+            let n = (window * window) as f64;
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covar = sum_ab / n - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covar + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+// From black through red and yellow to white
+fn heat(t: f64) -> Rgb<u8> {
+    let channel = |offset: f64| ((t * 3.0 - offset).clamp(0.0, 1.0) * 255.0) as u8;
+    Rgb([channel(0.0), channel(1.0), channel(2.0)])
+}
+
+// Both images need the same dimensions. The heatmap is scaled to the largest
+// error, so the worst areas are always the brightest.
+pub fn compare(original: &DynamicImage, output: &DynamicImage) -> (QualityMetrics, RgbImage) {
+    let a = premultiplied(original);
+    let b = premultiplied(output);
+    let (width, height) = a.dimensions();
+    let mut errors = vec![0u8; (width * height) as usize];
+    let mut squared_sum = 0u64;
+    for (i, (pa, pb)) in a.pixels().zip(b.pixels()).enumerate() {
+        for c in 0..3 {
+            let diff = pa[c].abs_diff(pb[c]);
+            squared_sum += diff as u64 * diff as u64;
+            errors[i] = errors[i].max(diff);
+        }
+    }
+    let max_error = errors.iter().copied().max().unwrap_or(0);
+    let mse = squared_sum as f64 / (width as f64 * height as f64 * 3.0);
+    let psnr = (mse > 0.0).then(|| 10.0 * (255.0 * 255.0 / mse).log10());
+    let heatmap = RgbImage::from_fn(width, height, |x, y| {
+        let error = errors[(y * width + x) as usize];
+        heat(error as f64 / max_error.max(1) as f64)
+    });
+    let metrics = QualityMetrics {
+        psnr,
+        ssim: ssim(&a, &b),
+        max_error: max_error as u32,
+    };
+    (metrics, heatmap)
+}
+
+fn read_oriented(path: &str, limits: &DecodeLimits) -> Result<DynamicImage, Error> {
+    let mut reader = image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| Error::io(path, e))?;
+    reader.limits(image_limits(limits));
+    let mut decoder = reader.into_decoder().map_err(|e| Error::image(path, e))?;
+    let orientation = decoder.orientation().map_err(|e| Error::image(path, e))?;
+    let mut img = DynamicImage::from_decoder(decoder).map_err(|e| Error::image(path, e))?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+pub fn measure(
+    original_path: &str,
+    output_path: &str,
+    limits: &DecodeLimits,
+) -> Result<(QualityMetrics, RgbImage), Error> {
+    let output = read_image(output_path, limits)?;
+    let original = read_image(original_path, limits)?;
+    measure_decoded(original_path, original, &output, limits)
+}
+
+// The original is brought to the output's shape first: rotated if the EXIF
+// orientation was applied, then resized if the profile resized it
+pub fn measure_decoded(
+    original_path: &str,
+    mut original: DynamicImage,
+    output: &DynamicImage,
+    limits: &DecodeLimits,
+) -> Result<(QualityMetrics, RgbImage), Error> {
+    if original.width() != output.width() || original.height() != output.height() {
+        original = read_oriented(original_path, limits)?;
+    }
+    if original.width() != output.width() || original.height() != output.height() {
+        original = original.resize_exact(output.width(), output.height(), FilterType::Lanczos3);
+    }
+    Ok(compare(&original, output))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn compare_images(
+    app: tauri::AppHandle,
+    original: String,
+    output: String,
+    heatmap_path: Option<String>,
+) -> Result<QualityMetrics, CompressError> {
+    let limits = settings::get_settings_data(&app).limits;
+    tauri::async_runtime::spawn_blocking(move || {
+        let (metrics, heatmap) = measure(&original, &output, &limits)?;
+        if let Some(heatmap_path) = heatmap_path {
+            heatmap
+                .save_with_format(&heatmap_path, image::ImageFormat::Png)
+                .map_err(|e| Error::image(&heatmap_path, e))?;
+        }
+        Ok::<_, Error>(metrics)
+    })
+    .await
+    .map_err(|e| CompressError::new(CompressErrorType::Unknown, e.to_string()))?
+    .map_err(CompressError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let original = DynamicImage::ImageRgb8(RgbImage::from_fn(32, 32, |x, y| {
+            Rgb([(x * 8) as u8, (y * 8) as u8, 128])
+        }));
+        let (metrics, heatmap) = compare(&original, &original);
+        assert_eq!(metrics.psnr, None);
+        assert!((metrics.ssim - 1.0).abs() < 1e-9);
+        assert_eq!(metrics.max_error, 0);
+        assert_eq!(heatmap.get_pixel(0, 0), &Rgb([0, 0, 0]));
+
+        let mut output = original.to_rgb8();
+        output.put_pixel(5, 5, Rgb([255, 255, 255]));
+        let (metrics, heatmap) = compare(&original, &DynamicImage::ImageRgb8(output));
+        assert!(metrics.psnr.unwrap() > 30.0);
+        assert!(metrics.ssim < 1.0);
+        assert_eq!(metrics.max_error, 255 - 40);
+        assert_eq!(heatmap.get_pixel(5, 5), &Rgb([255, 255, 255]));
+        assert_eq!(heatmap.get_pixel(6, 5), &Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_compare_ignores_transparent_colour() {
+        let a = RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 0, 0]));
+        let b = RgbaImage::from_pixel(8, 8, image::Rgba([0, 0, 255, 0]));
+        let (metrics, _) = compare(&DynamicImage::ImageRgba8(a), &DynamicImage::ImageRgba8(b));
+        assert_eq!(metrics.max_error, 0);
+    }
+
+    #[test]
+    fn test_measure_resized() {
+        let limits = DecodeLimits::default();
+        let original = read_image("test/test.jpg", &limits).unwrap();
+        let path = std::env::temp_dir().join("alic-test-metrics.png");
+        original
+            .resize_exact(
+                original.width() / 2,
+                original.height() / 2,
+                FilterType::Lanczos3,
+            )
+            .save(&path)
+            .unwrap();
+        let (metrics, heatmap) = measure("test/test.jpg", path.to_str().unwrap(), &limits).unwrap();
+        assert_eq!(heatmap.width(), original.width() / 2);
+        assert!(metrics.ssim > 0.99);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::compress::resize_image;
+use crate::error::{BoxError, Error};
+use crate::reduce::{add_reduction, to_palette, PixelReduction};
+use crate::settings::PngOptions;
+use caesium::parameters::CSParameters;
+use image::DynamicImage;
+use oxipng::{Interlacing, StripChunks};
+use std::fs;
+use std::io::Cursor;
+
+// `original_png` is the source path when it is already a PNG
+pub fn compress_png(
+    out_path: &str,
+    image: DynamicImage,
+    original_png: Option<&str>,
+    params: &CSParameters,
+    options: &PngOptions,
+    reductions: &mut Vec<PixelReduction>,
+) -> Result<(), Error> {
+    let png = compress_png_in_memory(out_path, image, original_png, params, options, reductions)?;
+    fs::write(out_path, png).map_err(|e| Error::io(out_path, e))
+}
+
+// `path` is only used in errors
+pub fn compress_png_in_memory(
+    path: &str,
+    image: DynamicImage,
+    original_png: Option<&str>,
+    params: &CSParameters,
+    options: &PngOptions,
+    reductions: &mut Vec<PixelReduction>,
+) -> Result<Vec<u8>, Error> {
+    let must_resize = params.width > 0 || params.height > 0;
+    let png = if let (true, false, Some(path)) = (options.lossless, must_resize, original_png) {
+        // Nothing to re-encode, let oxipng work on the original bytes
+        drop(image);
+        fs::read(path).map_err(|e| Error::io(path, e))?
+    } else {
+        let image = resize_image(image, params.width, params.height);
+        if options.lossless {
+            // Checked after resizing, which can add colors
+            match to_palette(&image) {
+                Some((palette, indexes)) => {
+                    add_reduction(reductions, PixelReduction::Palette);
+                    encode_indexed(&palette, &indexes, image.width(), image.height())
+                }
+                None => encode_png(&image),
+            }
+        } else {
+            quantize(&image, params.png.quality, options)
+        }
+        .map_err(|e| Error::encode(path, e))?
+    };
+    optimize(&png, params.png.optimization_level, options.interlace)
+        .map_err(|e| Error::encode(path, e))
+}
+
+fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, BoxError> {
+    let mut buffer = Vec::new();
+    image.write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::Png)?;
+    Ok(buffer)
+}
+
+fn encode_indexed(
+    palette: &[[u8; 4]],
+    indexes: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, BoxError> {
+    let palette: Vec<lodepng::RGBA> = palette
+        .iter()
+        .map(|&[r, g, b, a]| lodepng::RGBA::new(r, g, b, a))
+        .collect();
+    let mut encoder = lodepng::Encoder::new();
+    encoder.set_palette(&palette)?;
+    encoder
+        .encode(indexes, width as usize, height as usize)
+        .map_err(Into::into)
+}
+
+fn quantize(image: &DynamicImage, quality: u32, options: &PngOptions) -> Result<Vec<u8>, BoxError> {
+    let rgba = image.to_rgba8();
+    let (width, height) = (rgba.width() as usize, rgba.height() as usize);
+    let pixels: Vec<imagequant::RGBA> = rgba
+        .pixels()
+        .map(|p| imagequant::RGBA::new(p[0], p[1], p[2], p[3]))
+        .collect();
+    drop(rgba);
+
+    let mut liq = imagequant::new();
+    liq.set_quality(0, quality.min(100) as u8)?;
+    liq.set_max_colors(options.max_colors.clamp(2, 256))?;
+    let mut liq_image = liq.new_image(pixels, width, height, 0.0)?;
+    let mut quantization = liq.quantize(&mut liq_image)?;
+    quantization.set_dithering_level(options.dithering.min(100) as f32 / 100.0)?;
+    let (palette, indexes) = quantization.remapped(&mut liq_image)?;
+
+    let mut encoder = lodepng::Encoder::new();
+    encoder.set_palette(palette.as_slice())?;
+    encoder
+        .encode(indexes.as_slice(), width, height)
+        .map_err(Into::into)
+}
+
+fn optimize(png: &[u8], level: u8, interlace: bool) -> Result<Vec<u8>, BoxError> {
+    let mut options = oxipng::Options::from_preset(level.min(6));
+    options.strip = StripChunks::Safe;
+    options.interlace = Some(match interlace {
+        true => Interlacing::Adam7,
+        false => Interlacing::None,
+    });
+    Ok(oxipng::optimize_from_memory(png, &options)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+            image::Rgba([(x * 4) as u8, (y * 4) as u8, 128, 255])
+        }))
+    }
+
+    #[test]
+    fn test_quantize_max_colors() {
+        let options = PngOptions {
+            max_colors: 16,
+            ..PngOptions::default()
+        };
+        let png = quantize(&gradient(), 80, &options).unwrap();
+        let decoded = lodepng::decode32(&png).unwrap();
+        let mut colors: Vec<_> = decoded.buffer.iter().collect();
+        colors.sort_by_key(|c| (c.r, c.g, c.b, c.a));
+        colors.dedup();
+        assert!(colors.len() <= 16);
+    }
+
+    #[test]
+    fn test_lossless_keeps_pixels() {
+        let image = gradient();
+        let png = optimize(&encode_png(&image).unwrap(), 2, false).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap();
+        assert_eq!(decoded.to_rgba8(), image.to_rgba8());
+    }
+
+    #[test]
+    fn test_lossless_palette() {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+            image::Rgba([(x % 8 * 32) as u8, (y % 8 * 32) as u8, 0, 255])
+        }));
+        let params = CSParameters::new();
+        let options = PngOptions {
+            lossless: true,
+            ..PngOptions::default()
+        };
+        let mut reductions = vec![];
+        let png = compress_png_in_memory(
+            "test.png",
+            image.clone(),
+            None,
+            &params,
+            &options,
+            &mut reductions,
+        )
+        .unwrap();
+        assert_eq!(reductions, vec![PixelReduction::Palette]);
+        let decoded = image::load_from_memory(&png).unwrap();
+        assert_eq!(decoded.to_rgba8(), image.to_rgba8());
+    }
+
+    #[test]
+    fn test_interlace() {
+        let png = optimize(&encode_png(&gradient()).unwrap(), 2, true).unwrap();
+        // IHDR interlace method byte
+        assert_eq!(png[28], 1);
+    }
+}
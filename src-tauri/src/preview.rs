@@ -0,0 +1,181 @@
+use crate::compress::{encode_in_memory, read_image, CompressError, CompressErrorType, ImageType};
+use crate::error::Error;
+use crate::history::profile_hash;
+use crate::metrics::{measure_decoded, QualityMetrics};
+use crate::settings::{self, DecodeLimits, ProfileData};
+use base64::Engine;
+use image::{DynamicImage, ImageFormat};
+use specta::Type;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+// Enough for dragging a slider back and forth over one image
+const MAX_PREVIEWS: usize = 32;
+
+// The image being tuned, decoded once
+static DECODED: Mutex<Option<(FileKey, Arc<DynamicImage>)>> = Mutex::new(None);
+static PREVIEWS: Mutex<VecDeque<(String, Preview)>> = Mutex::new(VecDeque::new());
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+pub struct Preview {
+    // PNG data URL, scaled down to fit `max_size`
+    pub image: String,
+    pub size: u64,
+    pub original_size: u64,
+    pub out_type: ImageType,
+    // Of the encoded image, before scaling down
+    pub width: u32,
+    pub height: u32,
+    pub metrics: QualityMetrics,
+}
+
+// A file that changed on disk is decoded again
+#[derive(Debug, Clone, PartialEq)]
+struct FileKey {
+    path: String,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileKey {
+    fn new(path: &str) -> Result<Self, Error> {
+        let metadata = fs::metadata(path).map_err(|e| Error::io(path, e))?;
+        Ok(FileKey {
+            path: path.to_string(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+fn decoded(key: &FileKey, limits: &DecodeLimits) -> Result<Arc<DynamicImage>, Error> {
+    let mut decoded = DECODED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((cached_key, image)) = decoded.as_ref() {
+        if cached_key == key {
+            return Ok(image.clone());
+        }
+    }
+    let image = Arc::new(read_image(&key.path, limits)?);
+    *decoded = Some((key.clone(), image.clone()));
+    Ok(image)
+}
+
+fn data_url(image: &DynamicImage, path: &str) -> Result<String, Error> {
+    let mut png = Cursor::new(vec![]);
+    image
+        .write_to(&mut png, ImageFormat::Png)
+        .map_err(|e| Error::encode(path, e))?;
+    let data = base64::engine::general_purpose::STANDARD.encode(png.into_inner());
+    Ok(format!("data:image/png;base64,{}", data))
+}
+
+fn render_preview(
+    key: &FileKey,
+    parameters: &ProfileData,
+    max_size: u32,
+    limits: &DecodeLimits,
+) -> Result<Preview, Error> {
+    let path = key.path.as_str();
+    let original = decoded(key, limits)?;
+    let (bytes, out_type) = encode_in_memory(parameters, path, &original, limits)?;
+    let output = image::load_from_memory(&bytes).map_err(|e| Error::image(path, e))?;
+    let (metrics, _) = measure_decoded(path, (*original).clone(), &output, limits)?;
+    let (width, height) = (output.width(), output.height());
+    let shown = match max_size > 0 && (width > max_size || height > max_size) {
+        true => output.thumbnail(max_size, max_size),
+        false => output,
+    };
+    Ok(Preview {
+        image: data_url(&shown, path)?,
+        size: bytes.len() as u64,
+        original_size: key.size,
+        out_type,
+        width,
+        height,
+        metrics,
+    })
+}
+
+fn cached_preview(
+    key: &FileKey,
+    parameters: &ProfileData,
+    max_size: u32,
+    limits: &DecodeLimits,
+) -> Result<Preview, Error> {
+    let cache_key = format!(
+        "{}:{}:{:?}:{}:{}",
+        key.path,
+        key.size,
+        key.modified,
+        profile_hash(parameters),
+        max_size
+    );
+    let cached = PREVIEWS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|(k, _)| *k == cache_key)
+        .map(|(_, preview)| preview.clone());
+    if let Some(preview) = cached {
+        return Ok(preview);
+    }
+    let preview = render_preview(key, parameters, max_size, limits)?;
+    let mut previews = PREVIEWS.lock().unwrap_or_else(|e| e.into_inner());
+    // Other files' previews won't be asked for again
+    previews.retain(|(k, _)| k.starts_with(&format!("{}:", key.path)));
+    if previews.len() >= MAX_PREVIEWS {
+        previews.pop_front();
+    }
+    previews.push_back((cache_key, preview.clone()));
+    Ok(preview)
+}
+
+// Encodes with the given profile without writing anything, for tuning it
+#[tauri::command]
+#[specta::specta]
+pub async fn preview_image(
+    app: tauri::AppHandle,
+    path: String,
+    parameters: ProfileData,
+    max_size: u32,
+) -> Result<Preview, CompressError> {
+    let limits = settings::get_settings_data(&app).limits;
+    tauri::async_runtime::spawn_blocking(move || {
+        let key = FileKey::new(&path)?;
+        cached_preview(&key, &parameters, max_size, &limits)
+    })
+    .await
+    .map_err(|e| CompressError::new(CompressErrorType::Unknown, e.to_string()))?
+    .map_err(CompressError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview() {
+        let limits = DecodeLimits::default();
+        let key = FileKey::new("test/test.jpg").unwrap();
+        let mut parameters = ProfileData::new();
+        parameters.jpeg_quality = 40;
+
+        let preview = cached_preview(&key, &parameters, 64, &limits).unwrap();
+        assert_eq!(preview.out_type, ImageType::JPEG);
+        assert!(preview.size > 0 && preview.size < key.size);
+        assert!(preview.image.starts_with("data:image/png;base64,"));
+        assert!(preview.metrics.ssim > 0.5 && preview.metrics.ssim < 1.0);
+        assert_eq!(
+            cached_preview(&key, &parameters, 64, &limits).unwrap(),
+            preview
+        );
+
+        parameters.should_convert = true;
+        parameters.convert_extension = ImageType::PNG;
+        let preview = cached_preview(&key, &parameters, 64, &limits).unwrap();
+        assert_eq!(preview.out_type, ImageType::PNG);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::compress::{CompressError, CompressErrorType, FileEntry};
+use crate::error::Error;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::Manager;
+
+const QUEUE_FILE: &str = "queue.jsonl";
+
+static QUEUE: Mutex<Option<Queue>> = Mutex::new(None);
+
+#[derive(serde::Serialize, serde::Deserialize)]
+enum QueueLine {
+    File(FileEntry),
+    Removed(String),
+}
+
+// The file list as a log of changes, the last line for a path wins.
+// Appending keeps big batches cheap, it's compacted when loaded.
+struct Queue {
+    path: PathBuf,
+    entries: Vec<FileEntry>,
+    index: HashMap<String, usize>,
+}
+
+impl Queue {
+    fn open(path: &Path) -> Self {
+        let Ok(queue) = Queue::read(path) else {
+            return Queue {
+                path: path.to_path_buf(),
+                entries: vec![],
+                index: HashMap::new(),
+            };
+        };
+        if let Err(e) = queue.compact() {
+            println!("Cannot compact queue: {}", e);
+        }
+        queue
+    }
+
+    fn read(path: &Path) -> io::Result<Self> {
+        let mut queue = Queue {
+            path: path.to_path_buf(),
+            entries: vec![],
+            index: HashMap::new(),
+        };
+        BufReader::new(File::open(path)?)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .for_each(|line| queue.apply(line));
+        Ok(queue)
+    }
+
+    fn apply(&mut self, line: QueueLine) {
+        match line {
+            QueueLine::File(file) => match self.index.get(&file.path) {
+                Some(&i) => self.entries[i] = file,
+                None => {
+                    self.index.insert(file.path.clone(), self.entries.len());
+                    self.entries.push(file);
+                }
+            },
+            QueueLine::Removed(path) => {
+                if self.index.remove(&path).is_some() {
+                    self.entries.retain(|f| f.path != path);
+                    self.reindex();
+                }
+            }
+        }
+    }
+
+    fn reindex(&mut self) {
+        self.index = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (f.path.clone(), i))
+            .collect();
+    }
+
+    fn append(&mut self, lines: Vec<QueueLine>) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for line in &lines {
+            text.push_str(&serde_json::to_string(line)?);
+            text.push('\n');
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(text.as_bytes())?;
+        lines.into_iter().for_each(|line| self.apply(line));
+        Ok(())
+    }
+
+    fn compact(&self) -> io::Result<()> {
+        let mut text = String::new();
+        for file in &self.entries {
+            text.push_str(&serde_json::to_string(&QueueLine::File(file.clone()))?);
+            text.push('\n');
+        }
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, text)?;
+        fs::rename(temp_path, &self.path)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.index.clear();
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+// A queue file's entries, left as they are on disk
+pub fn read_queue(path: &Path) -> io::Result<Vec<FileEntry>> {
+    Queue::read(path).map(|queue| queue.entries)
+}
+
+fn with_queue<T>(
+    app: &tauri::AppHandle,
+    f: impl FnOnce(&mut Queue) -> io::Result<T>,
+) -> Result<T, CompressError> {
+    let path = app
+        .path()
+        .app_data_dir()
+        .map_err(|_| CompressError::new(CompressErrorType::Io, "Cannot find the app data folder"))?
+        .join(QUEUE_FILE);
+    let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+    if queue.as_ref().is_none_or(|q| q.path != path) {
+        *queue = Some(Queue::open(&path));
+    }
+    f(queue.as_mut().unwrap()).map_err(|e| Error::io(&path.to_string_lossy(), e).into())
+}
+
+// The list left from the last run, in the order files were added
+#[tauri::command]
+#[specta::specta]
+pub async fn get_queue(app: tauri::AppHandle) -> Result<Vec<FileEntry>, CompressError> {
+    with_queue(&app, |q| Ok(q.entries.clone()))
+}
+
+// Adds files or updates the ones already queued
+#[tauri::command]
+#[specta::specta]
+pub async fn queue_files(
+    app: tauri::AppHandle,
+    files: Vec<FileEntry>,
+) -> Result<(), CompressError> {
+    with_queue(&app, |q| {
+        q.append(files.into_iter().map(QueueLine::File).collect())
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn unqueue_files(app: tauri::AppHandle, paths: Vec<String>) -> Result<(), CompressError> {
+    with_queue(&app, |q| {
+        q.append(paths.into_iter().map(QueueLine::Removed).collect())
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn clear_queue(app: tauri::AppHandle) -> Result<(), CompressError> {
+    with_queue(&app, |q| q.clear())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::FileEntryStatus;
+
+    fn entry(path: &str, status: FileEntryStatus) -> FileEntry {
+        FileEntry {
+            path: path.to_string(),
+            file: None,
+            status,
+            size: None,
+            original_size: Some(100),
+            ext: None,
+            savings: Some(-12.5),
+            error: None,
+            out_path: None,
+            error_type: None,
+            width: None,
+            height: None,
+            image_type: None,
+            out_type: None,
+        }
+    }
+
+    #[test]
+    fn test_queue() {
+        let path = std::env::temp_dir().join("alic-test-queue.jsonl");
+        let _ = fs::remove_file(&path);
+        let mut queue = Queue::open(&path);
+        queue
+            .append(vec![
+                QueueLine::File(entry("/a.png", FileEntryStatus::Processing)),
+                QueueLine::File(entry("/b.png", FileEntryStatus::Processing)),
+                QueueLine::File(entry("/c.png", FileEntryStatus::Processing)),
+            ])
+            .unwrap();
+        queue
+            .append(vec![
+                QueueLine::File(entry("/a.png", FileEntryStatus::Complete)),
+                QueueLine::Removed("/b.png".to_string()),
+            ])
+            .unwrap();
+
+        // A crash mid write leaves a broken last line
+        write!(
+            OpenOptions::new().append(true).open(&path).unwrap(),
+            "{{\"Fi"
+        )
+        .unwrap();
+        let queue = Queue::open(&path);
+        assert_eq!(
+            queue.entries,
+            vec![
+                entry("/a.png", FileEntryStatus::Complete),
+                entry("/c.png", FileEntryStatus::Processing),
+            ]
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::compress::ImageType;
+use crate::settings::ProfileData;
+use image::DynamicImage;
+use specta::Type;
+use std::collections::HashMap;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize, Type)]
+pub enum PixelReduction {
+    To8Bit,
+    DropAlpha,
+    Grayscale,
+    Palette,
+}
+
+enum Samples<'a> {
+    U8(&'a [u8]),
+    U16(&'a [u16]),
+}
+
+// Applies the reductions that help the output format, adding them to `applied`
+pub fn reduce_for_output(
+    image: DynamicImage,
+    out_type: ImageType,
+    parameters: &ProfileData,
+    applied: &mut Vec<PixelReduction>,
+) -> DynamicImage {
+    if !parameters.reduce_pixel_format || !matches!(out_type, ImageType::PNG | ImageType::TIFF) {
+        return image;
+    }
+    let (image, reductions) = reduce_pixel_format(image);
+    for reduction in reductions {
+        add_reduction(applied, reduction);
+    }
+    image
+}
+
+pub fn add_reduction(applied: &mut Vec<PixelReduction>, reduction: PixelReduction) {
+    if !applied.contains(&reduction) {
+        applied.push(reduction);
+    }
+}
+
+pub fn reduce_pixel_format(image: DynamicImage) -> (DynamicImage, Vec<PixelReduction>) {
+    let samples = match &image {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageRgba8(_) => Samples::U8(image.as_bytes()),
+        DynamicImage::ImageLuma16(i) => Samples::U16(i.as_raw()),
+        DynamicImage::ImageLumaA16(i) => Samples::U16(i.as_raw()),
+        DynamicImage::ImageRgb16(i) => Samples::U16(i.as_raw()),
+        DynamicImage::ImageRgba16(i) => Samples::U16(i.as_raw()),
+        _ => return (image, vec![]),
+    };
+    let color = image.color();
+    let channels = color.channel_count() as usize;
+
+    let mut reductions = vec![];
+    let to_8bit = match samples {
+        Samples::U16(s) => s.iter().all(|v| v >> 8 == v & 0xff),
+        Samples::U8(_) => false,
+    };
+    if to_8bit {
+        reductions.push(PixelReduction::To8Bit);
+    }
+    let drop_alpha = color.has_alpha()
+        && match samples {
+            Samples::U8(s) => s.chunks_exact(channels).all(|p| p[channels - 1] == u8::MAX),
+            Samples::U16(s) => s
+                .chunks_exact(channels)
+                .all(|p| p[channels - 1] == u16::MAX),
+        };
+    if drop_alpha {
+        reductions.push(PixelReduction::DropAlpha);
+    }
+    let grayscale = color.has_color()
+        && match samples {
+            Samples::U8(s) => s
+                .chunks_exact(channels)
+                .all(|p| p[0] == p[1] && p[1] == p[2]),
+            Samples::U16(s) => s
+                .chunks_exact(channels)
+                .all(|p| p[0] == p[1] && p[1] == p[2]),
+        };
+    if grayscale {
+        reductions.push(PixelReduction::Grayscale);
+    }
+    if reductions.is_empty() {
+        return (image, reductions);
+    }
+
+    let sixteen_bit = matches!(samples, Samples::U16(_)) && !to_8bit;
+    let keep_color = color.has_color() && !grayscale;
+    let keep_alpha = color.has_alpha() && !drop_alpha;
+    let image = match (sixteen_bit, keep_color, keep_alpha) {
+        (false, false, false) => DynamicImage::ImageLuma8(image.to_luma8()),
+        (false, false, true) => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        (false, true, false) => DynamicImage::ImageRgb8(image.to_rgb8()),
+        (false, true, true) => DynamicImage::ImageRgba8(image.to_rgba8()),
+        (true, false, false) => DynamicImage::ImageLuma16(image.to_luma16()),
+        (true, false, true) => DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+        (true, true, false) => DynamicImage::ImageRgb16(image.to_rgb16()),
+        (true, true, true) => DynamicImage::ImageRgba16(image.to_rgba16()),
+    };
+    (image, reductions)
+}
+
+// Up to 256 RGBA colors and each pixel's index into them. 8-bit gray is
+// left alone, it's already a byte per pixel.
+pub fn to_palette(image: &DynamicImage) -> Option<(Vec<[u8; 4]>, Vec<u8>)> {
+    if !matches!(
+        image,
+        DynamicImage::ImageLumaA8(_) | DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_)
+    ) {
+        return None;
+    }
+    let channels = image.color().channel_count() as usize;
+    let mut colors = HashMap::new();
+    let mut palette = vec![];
+    let mut indexes = Vec::with_capacity(image.width() as usize * image.height() as usize);
+    for pixel in image.as_bytes().chunks_exact(channels) {
+        let rgba = match *pixel {
+            [v, a] => [v, v, v, a],
+            [r, g, b] => [r, g, b, u8::MAX],
+            [r, g, b, a] => [r, g, b, a],
+            _ => return None,
+        };
+        let index = match colors.get(&rgba) {
+            Some(&index) => index,
+            None if palette.len() == 256 => return None,
+            None => {
+                let index = palette.len() as u8;
+                colors.insert(rgba, index);
+                palette.push(rgba);
+                index
+            }
+        };
+        indexes.push(index);
+    }
+    Some((palette, indexes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reduce_pixel_format() {
+        let rgba16 = image::ImageBuffer::from_fn(16, 16, |x, y| {
+            let v = ((x * 16 + y) as u16) * 257;
+            image::Rgba([v, v, v, u16::MAX])
+        });
+        let image = DynamicImage::ImageRgba16(rgba16);
+        let (reduced, reductions) = reduce_pixel_format(image.clone());
+        assert_eq!(
+            reductions,
+            vec![
+                PixelReduction::To8Bit,
+                PixelReduction::DropAlpha,
+                PixelReduction::Grayscale
+            ]
+        );
+        assert_eq!(reduced.color(), image::ColorType::L8);
+        assert_eq!(reduced.to_rgba16(), image.to_rgba16());
+    }
+
+    #[test]
+    fn test_reduce_pixel_format_nothing_to_do() {
+        let rgba = image::RgbaImage::from_fn(4, 4, |x, y| image::Rgba([x as u8, y as u8, 0, 10]));
+        let (reduced, reductions) = reduce_pixel_format(DynamicImage::ImageRgba8(rgba));
+        assert!(reductions.is_empty());
+        assert_eq!(reduced.color(), image::ColorType::Rgba8);
+        let (palette, indexes) = to_palette(&reduced).unwrap();
+        assert_eq!((palette.len(), indexes.len()), (16, 16));
+        assert_eq!(palette[indexes[5] as usize], [1, 1, 0, 10]);
+    }
+}
@@ -0,0 +1,416 @@
+use crate::compress::{
+    read_image, CompressError, CompressErrorType, FileEntry, FileEntryStatus, ImageType,
+};
+use crate::error::Error;
+use crate::queue::read_queue;
+use crate::settings::{self, DecodeLimits};
+use base64::Engine;
+use image::{DynamicImage, ImageFormat};
+use specta::Type;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+const THUMBNAIL_SIZE: u32 = 96;
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+    Html,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+struct ReportRow<'a> {
+    path: &'a str,
+    out_path: Option<&'a str>,
+    original_size: Option<u64>,
+    out_size: Option<u64>,
+    bytes_saved: Option<i64>,
+    savings: Option<f64>,
+    width: Option<u32>,
+    height: Option<u32>,
+    format: Option<ImageType>,
+    out_format: Option<ImageType>,
+    status: FileEntryStatus,
+    error_type: Option<&'a CompressErrorType>,
+    error: Option<&'a str>,
+}
+
+impl<'a> ReportRow<'a> {
+    fn new(file: &'a FileEntry) -> Self {
+        let complete = file.status == FileEntryStatus::Complete;
+        let out_size = file.size.filter(|_| complete);
+        let bytes_saved = file
+            .original_size
+            .zip(out_size)
+            .map(|(original, out)| original as i64 - out as i64);
+        let savings = file
+            .original_size
+            .filter(|&original| original > 0)
+            .zip(bytes_saved)
+            .map(|(original, saved)| saved as f64 / original as f64 * 100.0);
+        ReportRow {
+            path: &file.path,
+            out_path: file.out_path.as_deref().filter(|_| complete),
+            original_size: file.original_size,
+            out_size,
+            bytes_saved,
+            savings,
+            width: file.width,
+            height: file.height,
+            format: file.image_type,
+            out_format: file.out_type.filter(|_| complete),
+            status: file.status,
+            error_type: file.error_type.as_ref(),
+            error: file.error.as_deref(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Default, PartialEq)]
+struct ReportTotals {
+    files: u32,
+    complete: u32,
+    errors: u32,
+    original_size: u64,
+    out_size: u64,
+    bytes_saved: i64,
+    savings: f64,
+}
+
+impl ReportTotals {
+    // Sizes only count images that were compressed
+    fn new(rows: &[ReportRow]) -> Self {
+        let mut totals = ReportTotals {
+            files: rows.len() as u32,
+            ..Default::default()
+        };
+        for row in rows {
+            match row.status {
+                FileEntryStatus::Complete => totals.complete += 1,
+                FileEntryStatus::Error => totals.errors += 1,
+                _ => {}
+            }
+            if let (Some(original), Some(out)) = (row.original_size, row.out_size) {
+                totals.original_size += original;
+                totals.out_size += out;
+            }
+        }
+        totals.bytes_saved = totals.original_size as i64 - totals.out_size as i64;
+        if totals.original_size > 0 {
+            totals.savings = totals.bytes_saved as f64 / totals.original_size as f64 * 100.0;
+        }
+        totals
+    }
+}
+
+#[derive(serde::Serialize)]
+struct JsonReport<'a> {
+    totals: ReportTotals,
+    files: Vec<ReportRow<'a>>,
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn human_size(bytes: i64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes.unsigned_abs() as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    let sign = if bytes < 0 { "-" } else { "" };
+    format!("{}{:.1} {}", sign, size, units[unit])
+}
+
+fn to_csv(rows: &[ReportRow]) -> String {
+    let mut csv = String::from(
+        "path,out_path,original_size,out_size,bytes_saved,savings,width,height,format,out_format,status,error_type,error\n",
+    );
+    for row in rows {
+        let fields = [
+            row.path.to_string(),
+            optional(row.out_path),
+            optional(row.original_size),
+            optional(row.out_size),
+            optional(row.bytes_saved),
+            optional(row.savings.map(|s| format!("{:.1}", s))),
+            optional(row.width),
+            optional(row.height),
+            optional(row.format.map(|t| format!("{:?}", t))),
+            optional(row.out_format.map(|t| format!("{:?}", t))),
+            format!("{:?}", row.status),
+            optional(row.error_type.map(|t| format!("{:?}", t))),
+            optional(row.error),
+        ];
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+// A small JPEG inlined as a data URI, so the report is a single file
+fn thumbnail(path: &str, limits: &DecodeLimits) -> Option<String> {
+    let img = read_image(path, limits).ok()?;
+    let thumb = DynamicImage::ImageRgb8(img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgb8());
+    let mut bytes = Cursor::new(vec![]);
+    thumb.write_to(&mut bytes, ImageFormat::Jpeg).ok()?;
+    let data = base64::engine::general_purpose::STANDARD.encode(bytes.into_inner());
+    Some(format!("data:image/jpeg;base64,{}", data))
+}
+
+fn to_html(rows: &[ReportRow], totals: &ReportTotals, limits: &DecodeLimits) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Alic Report</title>\n<style>\nbody { font-family: sans-serif; margin: 2em; }\ntable { border-collapse: collapse; width: 100%; }\nth, td { border-bottom: 1px solid #ddd; padding: 4px 8px; text-align: left; }\nimg { max-width: 96px; max-height: 96px; }\n.error { color: #b00; }\n</style>\n</head>\n<body>\n<h1>Alic Report</h1>\n",
+    );
+    html.push_str(&format!(
+        "<p>{} images, {} compressed, {} errors. {} saved of {} ({:.1}%).</p>\n",
+        totals.files,
+        totals.complete,
+        totals.errors,
+        human_size(totals.bytes_saved),
+        human_size(totals.original_size as i64),
+        totals.savings,
+    ));
+    html.push_str("<table>\n<tr><th></th><th>File</th><th>Dimensions</th><th>Format</th><th>Size</th><th>Savings</th><th>Status</th></tr>\n");
+    for row in rows {
+        // The output is what the client gets, the original shows what failed
+        let thumb = thumbnail(row.out_path.unwrap_or(row.path), limits)
+            .map(|src| format!("<img src=\"{}\">", src))
+            .unwrap_or_default();
+        let dimensions = match (row.width, row.height) {
+            (Some(w), Some(h)) => format!("{} x {}", w, h),
+            _ => String::new(),
+        };
+        let format = match (row.format, row.out_format) {
+            (Some(format), Some(out_format)) if format != out_format => format!(
+                "{} &rarr; {}",
+                html_escape(&format!("{:?}", format)),
+                html_escape(&format!("{:?}", out_format))
+            ),
+            (format, out_format) => {
+                html_escape(&optional(format.or(out_format).map(|t| format!("{:?}", t))))
+            }
+        };
+        let size = match (row.original_size, row.out_size) {
+            (Some(original), Some(out)) => format!(
+                "{} &rarr; {}",
+                human_size(original as i64),
+                human_size(out as i64)
+            ),
+            (Some(original), None) => human_size(original as i64),
+            _ => String::new(),
+        };
+        let savings = optional(row.savings.map(|s| format!("{:.1}%", s)));
+        let status = match row.error {
+            Some(error) if row.status != FileEntryStatus::Complete => format!(
+                "<span class=\"error\">{:?}: {}</span>",
+                row.status,
+                html_escape(error)
+            ),
+            _ => format!("{:?}", row.status),
+        };
+        let file = match row.out_path {
+            Some(out_path) if out_path != row.path => format!(
+                "{}<br><small>{}</small>",
+                html_escape(row.path),
+                html_escape(out_path)
+            ),
+            _ => html_escape(row.path),
+        };
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            thumb, file, dimensions, format, size, savings, status
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+// Kept apart from the command so it can be reused without a window
+pub fn write_report(
+    files: &[FileEntry],
+    format: ReportFormat,
+    out_path: &Path,
+    limits: &DecodeLimits,
+) -> std::io::Result<()> {
+    let rows: Vec<ReportRow> = files.iter().map(ReportRow::new).collect();
+    let totals = ReportTotals::new(&rows);
+    let text = match format {
+        ReportFormat::Csv => to_csv(&rows),
+        ReportFormat::Json => serde_json::to_string_pretty(&JsonReport {
+            totals,
+            files: rows,
+        })?,
+        ReportFormat::Html => to_html(&rows, &totals, limits),
+    };
+    fs::write(out_path, text)
+}
+
+const CLI_USAGE: &str = "Usage: alic report <queue.jsonl> <report.csv|report.json|report.html>";
+
+// `alic report`, for the batch saved in a queue file. The format is picked
+// from the report's extension.
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+    let [queue_path, out_path] = args else {
+        return Err(CLI_USAGE.to_string());
+    };
+    let out_path = Path::new(out_path);
+    let extension = out_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let format = match extension.as_str() {
+        "csv" => ReportFormat::Csv,
+        "json" => ReportFormat::Json,
+        "html" | "htm" => ReportFormat::Html,
+        _ => return Err(CLI_USAGE.to_string()),
+    };
+    let files = read_queue(Path::new(queue_path))
+        .map_err(|e| format!("Cannot read {}: {}", queue_path, e))?;
+    write_report(&files, format, out_path, &DecodeLimits::default())
+        .map_err(|e| format!("Cannot write report: {}", e))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn export_report(
+    app: tauri::AppHandle,
+    files: Vec<FileEntry>,
+    format: ReportFormat,
+    path: String,
+) -> Result<(), CompressError> {
+    let limits = settings::get_settings_data(&app).limits;
+    // Thumbnails decode every image, so it stays off the main thread
+    tauri::async_runtime::spawn_blocking(move || {
+        write_report(&files, format, Path::new(&path), &limits).map_err(|e| Error::io(&path, e))
+    })
+    .await
+    .map_err(|e| CompressError::new(CompressErrorType::Unknown, e.to_string()))?
+    .map_err(CompressError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, status: FileEntryStatus) -> FileEntry {
+        FileEntry {
+            path: path.to_string(),
+            file: None,
+            status,
+            size: Some(400),
+            original_size: Some(1000),
+            ext: None,
+            savings: None,
+            error: None,
+            out_path: Some("/out/a, b.webp".to_string()),
+            error_type: None,
+            width: Some(10),
+            height: Some(20),
+            image_type: None,
+            out_type: None,
+        }
+    }
+
+    #[test]
+    fn test_report_rows() {
+        let mut complete = entry("/in/a.png", FileEntryStatus::Complete);
+        complete.image_type = Some(ImageType::PNG);
+        complete.out_type = Some(ImageType::WEBP);
+        let mut failed = entry("/in/b.jpg", FileEntryStatus::Error);
+        failed.image_type = Some(ImageType::JPEG);
+        failed.error = Some("Cannot decode \"b.jpg\"".to_string());
+        failed.error_type = Some(CompressErrorType::Decode);
+        let files = [complete, failed];
+        let rows: Vec<ReportRow> = files.iter().map(ReportRow::new).collect();
+
+        assert_eq!(rows[0].bytes_saved, Some(600));
+        assert_eq!(rows[0].savings, Some(60.0));
+        assert_eq!(rows[0].out_format, Some(ImageType::WEBP));
+        assert_eq!(rows[1].out_size, None);
+        assert_eq!(rows[1].out_path, None);
+
+        let totals = ReportTotals::new(&rows);
+        assert_eq!((totals.files, totals.complete, totals.errors), (2, 1, 1));
+        assert_eq!(totals.bytes_saved, 600);
+
+        let csv = to_csv(&rows);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[1],
+            "/in/a.png,\"/out/a, b.webp\",1000,400,600,60.0,10,20,PNG,WEBP,Complete,,"
+        );
+        assert_eq!(
+            lines[2],
+            "/in/b.jpg,,1000,,,,10,20,JPEG,,Error,Decode,\"Cannot decode \"\"b.jpg\"\"\""
+        );
+    }
+
+    #[test]
+    fn test_run_cli() {
+        let dir = std::env::temp_dir();
+        let queue_path = dir.join("alic-test-report-queue.jsonl");
+        let mut file = entry("/in/a.png", FileEntryStatus::Complete);
+        file.image_type = Some(ImageType::PNG);
+        let line = format!("{{\"File\":{}}}\n", serde_json::to_string(&file).unwrap());
+        fs::write(&queue_path, line).unwrap();
+        let out_path = dir.join("alic-test-report-cli.csv");
+        let args = |out: &Path| {
+            vec![
+                queue_path.to_string_lossy().to_string(),
+                out.to_string_lossy().to_string(),
+            ]
+        };
+
+        run_cli(&args(&out_path)).unwrap();
+        let csv = fs::read_to_string(&out_path).unwrap();
+        assert!(csv.lines().nth(1).unwrap().starts_with("/in/a.png,"));
+        assert_eq!(
+            run_cli(&args(Path::new("report.txt"))),
+            Err(CLI_USAGE.to_string())
+        );
+        assert!(run_cli(&args(&out_path)[..1]).is_err());
+    }
+
+    #[test]
+    fn test_write_report() {
+        let dir = std::env::temp_dir();
+        let mut file = entry("test/test.jpg", FileEntryStatus::Complete);
+        file.out_path = None;
+        let files = [file, entry("/in/<b>.png", FileEntryStatus::Error)];
+        let limits = DecodeLimits::default();
+
+        let path = dir.join("alic-test-report.json");
+        write_report(&files, ReportFormat::Json, &path, &limits).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["totals"]["complete"], 1);
+        assert_eq!(json["files"][1]["status"], "Error");
+
+        let path = dir.join("alic-test-report.html");
+        write_report(&files, ReportFormat::Html, &path, &limits).unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        assert!(html.contains("data:image/jpeg;base64,"));
+        assert!(html.contains("&lt;b&gt;.png"));
+    }
+}
@@ -0,0 +1,404 @@
+use crate::compress::{guess_image_type, CompressError, CompressErrorType};
+use crate::events::{emit_add_files, emit_scan_error, emit_scan_progress};
+use crate::ignore::{is_ignored, IgnoreFile, RuleSet};
+use crate::settings::{self, ScanOptions, SymlinkPolicy};
+use specta::Type;
+use std::collections::HashSet;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const IMAGE_EXTENSIONS: [&str; 7] = ["png", "jpeg", "jpg", "gif", "webp", "tiff", "tif"];
+const BATCH_SIZE: usize = 500;
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Bumped by cancel_scan, a scan stops once it no longer matches the one it started with
+static SCAN_GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Type)]
+pub struct ScanProgress {
+    pub root: String,
+    pub scanned_dirs: u32,
+    pub found: u32,
+    pub errors: u32,
+    pub done: bool,
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Type)]
+pub struct ScanError {
+    pub root: String,
+    pub path: String,
+    pub error: String,
+}
+
+pub enum ScanEvent {
+    Files(Vec<String>),
+    Progress(ScanProgress),
+    Error(ScanError),
+}
+
+// Walks on a blocking thread and streams what it finds as `add-files` batches
+#[tauri::command]
+#[specta::specta]
+pub async fn get_all_images(app: tauri::AppHandle, path: String) -> Result<(), CompressError> {
+    let settings = settings::get_settings_data(&app);
+    let options = settings
+        .profiles
+        .iter()
+        .find(|p| p.active)
+        .map(|p| p.scan.clone())
+        .unwrap_or_default();
+    let generation = SCAN_GENERATION.load(Ordering::SeqCst);
+    tauri::async_runtime::spawn_blocking(move || {
+        scan_images(
+            Path::new(&path),
+            &options,
+            settings.include_extensionless,
+            || SCAN_GENERATION.load(Ordering::SeqCst) != generation,
+            |event| match event {
+                ScanEvent::Files(paths) => emit_add_files(&app, paths),
+                ScanEvent::Progress(progress) => emit_scan_progress(&app, progress),
+                ScanEvent::Error(error) => emit_scan_error(&app, error),
+            },
+        )
+    })
+    .await
+    .map_err(|e| CompressError::new(CompressErrorType::Unknown, e.to_string()))
+}
+
+// Stops every scan that is running
+#[tauri::command]
+#[specta::specta]
+pub async fn cancel_scan() {
+    SCAN_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn scan_images<C, F>(
+    root: &Path,
+    options: &ScanOptions,
+    include_extensionless: bool,
+    is_cancelled: C,
+    on_event: F,
+) where
+    C: Fn() -> bool,
+    F: FnMut(ScanEvent),
+{
+    let mut scanner = Scanner {
+        progress: ScanProgress {
+            root: root.to_string_lossy().to_string(),
+            scanned_dirs: 0,
+            found: 0,
+            errors: 0,
+            done: false,
+            cancelled: false,
+        },
+        root: root.to_path_buf(),
+        options: options.clone(),
+        include: RuleSet::from_globs(&options.include),
+        exclude: RuleSet::from_globs(&options.exclude),
+        include_extensionless,
+        visited: HashSet::new(),
+        batch: vec![],
+        last_flush: Instant::now(),
+        on_event,
+    };
+    if root.is_file() {
+        // Picked by hand, so the extension doesn't matter
+        if is_image(root, true) {
+            scanner.add(root);
+        }
+    } else if root.is_dir() {
+        // A stack instead of recursion, deep trees can't overflow it
+        let mut dirs = vec![Dir {
+            path: root.to_path_buf(),
+            depth: 1,
+            ignores: Arc::new(vec![]),
+        }];
+        scanner.visit(root);
+        while let Some(dir) = dirs.pop() {
+            if is_cancelled() {
+                scanner.progress.cancelled = true;
+                break;
+            }
+            scanner.scan_dir(dir, &mut dirs);
+        }
+    }
+    scanner.progress.done = true;
+    scanner.flush();
+}
+
+struct Dir {
+    path: PathBuf,
+    depth: u32,
+    // From the scan root down to this directory
+    ignores: Arc<Vec<IgnoreFile>>,
+}
+
+struct Scanner<F: FnMut(ScanEvent)> {
+    progress: ScanProgress,
+    root: PathBuf,
+    options: ScanOptions,
+    include: RuleSet,
+    exclude: RuleSet,
+    include_extensionless: bool,
+    // Canonical paths of the directories seen, only kept when following symlinks
+    visited: HashSet<PathBuf>,
+    batch: Vec<String>,
+    last_flush: Instant,
+    on_event: F,
+}
+
+impl<F: FnMut(ScanEvent)> Scanner<F> {
+    fn scan_dir(&mut self, dir: Dir, dirs: &mut Vec<Dir>) {
+        self.progress.scanned_dirs += 1;
+        let entries = match fs::read_dir(&dir.path) {
+            Ok(entries) => entries,
+            Err(err) => return self.error(&dir.path, err),
+        };
+        let ignores = match self.options.use_ignore_files {
+            true => match IgnoreFile::load(&dir.path) {
+                Some(ignore) => {
+                    let mut ignores = Vec::clone(&dir.ignores);
+                    ignores.push(ignore);
+                    Arc::new(ignores)
+                }
+                None => dir.ignores.clone(),
+            },
+            false => dir.ignores.clone(),
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    self.error(&dir.path, err);
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if is_symlink && self.options.symlinks == SymlinkPolicy::Skip {
+                continue;
+            }
+            let is_dir = path.is_dir();
+            let relative = path.strip_prefix(&self.root).unwrap_or(&path);
+            if self.exclude.matched(relative, is_dir) == Some(true)
+                || is_ignored(&ignores, &path, is_dir)
+            {
+                continue;
+            }
+            if is_dir {
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                let too_deep = self.options.max_depth > 0 && dir.depth >= self.options.max_depth;
+                if (hidden && self.options.skip_hidden) || too_deep || !self.visit(&path) {
+                    continue;
+                }
+                dirs.push(Dir {
+                    path,
+                    depth: dir.depth + 1,
+                    ignores: ignores.clone(),
+                });
+            } else if (self.include.is_empty()
+                || self.include.matched(relative, false) == Some(true))
+                && is_image(&path, self.include_extensionless)
+            {
+                self.add(&path);
+            }
+        }
+    }
+
+    // False if the directory was already scanned through another link
+    fn visit(&mut self, path: &Path) -> bool {
+        if self.options.symlinks == SymlinkPolicy::Skip {
+            return true;
+        }
+        match fs::canonicalize(path) {
+            Ok(path) => self.visited.insert(path),
+            Err(_) => false,
+        }
+    }
+
+    fn add(&mut self, path: &Path) {
+        self.batch.push(path.to_string_lossy().to_string());
+        self.progress.found += 1;
+        if self.batch.len() >= BATCH_SIZE || self.last_flush.elapsed() >= BATCH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    fn error(&mut self, path: &Path, err: std::io::Error) {
+        self.progress.errors += 1;
+        (self.on_event)(ScanEvent::Error(ScanError {
+            root: self.progress.root.clone(),
+            path: path.to_string_lossy().to_string(),
+            error: err.to_string(),
+        }));
+    }
+
+    fn flush(&mut self) {
+        if !self.batch.is_empty() {
+            (self.on_event)(ScanEvent::Files(mem::take(&mut self.batch)));
+        }
+        (self.on_event)(ScanEvent::Progress(self.progress.clone()));
+        self.last_flush = Instant::now();
+    }
+}
+
+// The extension only rules files out cheaply, the magic bytes decide,
+// so a scan accepts exactly what compression will
+fn is_image(path: &Path, include_extensionless: bool) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    match path.extension() {
+        Some(ext) => {
+            let ext = ext.to_string_lossy().to_lowercase();
+            if !IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+                return false;
+            }
+        }
+        None if !include_extensionless => return false,
+        None => {}
+    }
+    guess_image_type(&path.to_string_lossy()).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_image() {
+        let dir = std::env::temp_dir().join("alic_is_image");
+        let _ = fs::create_dir_all(&dir);
+        fs::copy("test/test.jpg", dir.join("IMG_001.JPG")).unwrap();
+        fs::copy("test/test.jpg", dir.join("download")).unwrap();
+        fs::write(dir.join("fake.png"), "not an image").unwrap();
+
+        assert!(is_image(&dir.join("IMG_001.JPG"), false));
+        assert!(!is_image(&dir.join("download"), false));
+        assert!(is_image(&dir.join("download"), true));
+        assert!(!is_image(&dir.join("fake.png"), true));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_scan_images() {
+        let dir = std::env::temp_dir().join("alic_scan");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::copy("test/test.jpg", dir.join("one.jpg")).unwrap();
+        fs::copy("test/test.jpg", dir.join("a/b/two.jpg")).unwrap();
+        fs::write(dir.join("a/notes.txt"), "").unwrap();
+
+        let mut files = vec![];
+        let mut last = None;
+        scan_images(
+            &dir,
+            &ScanOptions::default(),
+            false,
+            || false,
+            |event| match event {
+                ScanEvent::Files(paths) => files.extend(paths),
+                ScanEvent::Progress(progress) => last = Some(progress),
+                ScanEvent::Error(_) => {}
+            },
+        );
+        files.sort();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].ends_with("two.jpg"));
+        let last = last.unwrap();
+        assert!(last.done && !last.cancelled);
+        assert_eq!((last.scanned_dirs, last.found), (3, 2));
+
+        let mut found = 0;
+        let mut cancelled = false;
+        scan_images(
+            &dir,
+            &ScanOptions::default(),
+            false,
+            || true,
+            |event| match event {
+                ScanEvent::Files(paths) => found += paths.len(),
+                ScanEvent::Progress(progress) => cancelled = progress.cancelled,
+                ScanEvent::Error(_) => {}
+            },
+        );
+        assert_eq!(found, 0);
+        assert!(cancelled);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_scan_options() {
+        let dir = std::env::temp_dir().join("alic_scan_options");
+        let _ = fs::remove_dir_all(&dir);
+        for sub in ["node_modules", ".cache", "build", "a/b"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            fs::copy("test/test.jpg", dir.join(sub).join("x.jpg")).unwrap();
+        }
+        fs::copy("test/test.jpg", dir.join("top.jpg")).unwrap();
+        fs::write(dir.join(".gitignore"), "build/\n").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("a/loop")).unwrap();
+
+        let scan = |options: &ScanOptions| {
+            let mut files = vec![];
+            scan_images(
+                &dir,
+                options,
+                false,
+                || false,
+                |event| {
+                    if let ScanEvent::Files(paths) = event {
+                        files.extend(paths);
+                    }
+                },
+            );
+            files.sort();
+            files
+        };
+        let relative = |files: Vec<String>| -> Vec<String> {
+            files
+                .iter()
+                .map(|f| f.trim_start_matches(&*dir.to_string_lossy()).to_string())
+                .collect()
+        };
+
+        let defaults = ScanOptions::default();
+        assert_eq!(relative(scan(&defaults)), vec!["/a/b/x.jpg", "/top.jpg"]);
+
+        let shallow = ScanOptions {
+            max_depth: 1,
+            ..ScanOptions::default()
+        };
+        assert_eq!(relative(scan(&shallow)), vec!["/top.jpg"]);
+
+        let included = ScanOptions {
+            include: vec!["a/**/*.jpg".to_string()],
+            ..ScanOptions::default()
+        };
+        assert_eq!(relative(scan(&included)), vec!["/a/b/x.jpg"]);
+
+        // The link back to the root is followed once, then recognised
+        let follow = ScanOptions {
+            symlinks: SymlinkPolicy::Follow,
+            ..ScanOptions::default()
+        };
+        assert_eq!(relative(scan(&follow)), vec!["/a/b/x.jpg", "/top.jpg"]);
+
+        let everything = ScanOptions {
+            exclude: vec![],
+            use_ignore_files: false,
+            skip_hidden: false,
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan(&everything).len(), 5);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
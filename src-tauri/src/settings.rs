@@ -6,7 +6,7 @@ use std::sync::Arc;
 use tauri::Wry;
 use tauri_plugin_store::{Store, StoreExt};
 
-use crate::compress::ImageType;
+use crate::compress::{CompressError, CompressErrorType, ImageType};
 
 const SETTINGS_KEY: &str = "settings";
 
@@ -16,9 +16,59 @@ pub struct SettingsData {
     pub theme: ThemeKind,
     #[serde(default)]
     pub threads: i32,
+    // Decoded image memory shared by parallel jobs in MB, 0 for the default
+    #[serde(default)]
+    pub memory_budget: u32,
+    #[serde(default)]
+    pub limits: DecodeLimits,
+    // Seconds a single image may take, 0 for no limit
+    #[serde(default = "default_time_limit")]
+    pub time_limit: u32,
+    // Sniff files without an extension when scanning folders
+    #[serde(default)]
+    pub include_extensionless: bool,
+    // Compress files again even if the history says it's been done with the same profile
+    #[serde(default)]
+    pub recompress_processed: bool,
+    // Keep a copy of every file that gets overwritten or trashed, so it can be undone
+    #[serde(default)]
+    pub backup_originals: bool,
+    // Compare every output to its original, this decodes both again
+    #[serde(default)]
+    pub measure_quality: bool,
     pub profiles: Vec<ProfileData>,
 }
 
+// Guards against decompression bombs, 0 disables a limit
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
+pub struct DecodeLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_alloc_mb: u32,
+    pub max_file_mb: u32,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_width: 20000,
+            max_height: 20000,
+            max_alloc_mb: 2048,
+            // Big scans are fine, the size on disk says little about the decoded size
+            max_file_mb: 0,
+        }
+    }
+}
+
+// Settings saved before a field existed get the same value as new ones
+fn default_time_limit() -> u32 {
+    300
+}
+
+fn default_true() -> bool {
+    true
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
 pub enum ThemeKind {
     Light,
@@ -32,6 +82,13 @@ impl SettingsData {
             version: 1,
             theme: ThemeKind::System,
             threads: 0,
+            memory_budget: 0,
+            limits: DecodeLimits::default(),
+            time_limit: default_time_limit(),
+            include_extensionless: false,
+            recompress_processed: false,
+            backup_originals: false,
+            measure_quality: false,
             profiles: vec![ProfileData::new()],
         }
     }
@@ -55,6 +112,204 @@ pub struct ProfileData {
     pub png_quality: u32,
     pub webp_quality: u32,
     pub gif_quality: u32,
+    // Lossless bit depth, alpha and grayscale reduction before encoding
+    #[serde(default = "default_true")]
+    pub reduce_pixel_format: bool,
+    #[serde(default)]
+    pub jpeg: JpegOptions,
+    #[serde(default)]
+    pub png: PngOptions,
+    #[serde(default)]
+    pub webp: WebpOptions,
+    #[serde(default)]
+    pub tiff: TiffOptions,
+    #[serde(default)]
+    pub scan: ScanOptions,
+    #[serde(default)]
+    pub filters: FileFilters,
+}
+
+// Which files get compressed, the rest are marked Skipped. 0 leaves a bound unset.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, Default)]
+pub struct FileFilters {
+    pub min_size_kb: u32,
+    pub max_size_kb: u32,
+    pub min_width: u32,
+    pub max_width: u32,
+    pub min_height: u32,
+    pub max_height: u32,
+    // Unix seconds
+    pub modified_after: u64,
+    // Compress when any of size, dimensions or modification time matches, instead of all
+    pub match_any: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, Copy, PartialEq, Default)]
+pub enum SymlinkPolicy {
+    #[default]
+    Skip,
+    // Each directory is visited once, so links back up the tree can't loop
+    Follow,
+}
+
+// How dropped folders are walked
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
+pub struct ScanOptions {
+    // Globs for files to add, empty adds every image
+    pub include: Vec<String>,
+    // Globs for files and folders to leave out
+    pub exclude: Vec<String>,
+    // Honor .gitignore and .alicignore files
+    pub use_ignore_files: bool,
+    // Folder levels to descend, 1 is only the dropped folder and 0 is no limit
+    pub max_depth: u32,
+    pub skip_hidden: bool,
+    pub symlinks: SymlinkPolicy,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            include: vec![],
+            exclude: vec!["node_modules".to_string()],
+            use_ignore_files: true,
+            max_depth: 0,
+            skip_hidden: true,
+            symlinks: SymlinkPolicy::Skip,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, Copy, PartialEq)]
+pub enum ChromaSubsampling {
+    CS444,
+    CS422,
+    CS420,
+    Auto,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, Copy, PartialEq, Default)]
+pub enum JpegRotation {
+    None,
+    // Follow the EXIF orientation
+    #[default]
+    Auto,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+// Huffman tables are always optimized by mozjpeg, so there is no option for it.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
+pub struct JpegOptions {
+    pub progressive: bool,
+    pub chroma_subsampling: ChromaSubsampling,
+    // Minimum quality used for images that look like text or screenshots, 0 to disable
+    pub text_quality_floor: u32,
+    // Never re-encode, quality, subsampling and resize are ignored
+    #[serde(default)]
+    pub lossless: bool,
+    // Only used in lossless mode
+    #[serde(default)]
+    pub rotation: JpegRotation,
+    // Lossless mode copies EXIF and the other metadata instead of stripping it
+    #[serde(default)]
+    pub keep_metadata: bool,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        Self {
+            progressive: true,
+            chroma_subsampling: ChromaSubsampling::Auto,
+            text_quality_floor: 0,
+            lossless: false,
+            rotation: JpegRotation::Auto,
+            keep_metadata: false,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
+pub struct PngOptions {
+    // Only optimize, never reduce the palette
+    pub lossless: bool,
+    pub max_colors: u32,
+    // 0-100
+    pub dithering: u32,
+    // oxipng preset, 0-6
+    pub optimization_level: u32,
+    pub interlace: bool,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self {
+            lossless: false,
+            max_colors: 256,
+            dithering: 100,
+            optimization_level: 3,
+            interlace: false,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, Copy, PartialEq)]
+pub enum WebpMode {
+    Lossy,
+    Lossless,
+    // Lossless with webp_quality used as the near-lossless level
+    NearLossless,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
+pub struct WebpOptions {
+    pub mode: WebpMode,
+    // Encoding effort, 0 (fastest) to 6 (slowest)
+    pub method: u32,
+    pub alpha_quality: u32,
+    pub sharp_yuv: bool,
+}
+
+impl Default for WebpOptions {
+    fn default() -> Self {
+        Self {
+            mode: WebpMode::Lossy,
+            method: 4,
+            alpha_quality: 100,
+            sharp_yuv: false,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, Copy, PartialEq)]
+pub enum TiffCompression {
+    None,
+    Lzw,
+    Deflate,
+    PackBits,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, Copy, PartialEq)]
+pub enum TiffDeflateLevel {
+    Fast,
+    Balanced,
+    Best,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
+pub struct TiffOptions {
+    pub compression: TiffCompression,
+    pub deflate_level: TiffDeflateLevel,
+}
+
+impl Default for TiffOptions {
+    fn default() -> Self {
+        Self {
+            compression: TiffCompression::Deflate,
+            deflate_level: TiffDeflateLevel::Balanced,
+        }
+    }
 }
 
 impl ProfileData {
@@ -75,6 +330,13 @@ impl ProfileData {
             png_quality: 80,
             webp_quality: 80,
             gif_quality: 80,
+            reduce_pixel_format: true,
+            jpeg: JpegOptions::default(),
+            png: PngOptions::default(),
+            webp: WebpOptions::default(),
+            tiff: TiffOptions::default(),
+            scan: ScanOptions::default(),
+            filters: FileFilters::default(),
         }
     }
 
@@ -89,31 +351,37 @@ impl ProfileData {
 
 #[tauri::command]
 #[specta::specta]
-pub async fn get_settings(app: tauri::AppHandle) -> Result<SettingsData, String> {
+pub async fn get_settings(app: tauri::AppHandle) -> Result<SettingsData, CompressError> {
     Ok(get_settings_data(&app))
 }
 
 #[tauri::command]
 #[specta::specta]
-pub async fn save_settings(app: tauri::AppHandle, settings: SettingsData) -> Result<(), String> {
+pub async fn save_settings(
+    app: tauri::AppHandle,
+    settings: SettingsData,
+) -> Result<(), CompressError> {
     set_settings_data(&app, settings);
     Ok(())
 }
 
 #[tauri::command]
 #[specta::specta]
-pub async fn reset_settings(app: tauri::AppHandle) -> Result<(), String> {
+pub async fn reset_settings(app: tauri::AppHandle) -> Result<(), CompressError> {
     set_settings_data(&app, SettingsData::new());
     Ok(())
 }
 
 #[tauri::command]
 #[specta::specta]
-pub async fn reset_profile(app: tauri::AppHandle, profile_id: u32) -> Result<(), String> {
+pub async fn reset_profile(app: tauri::AppHandle, profile_id: u32) -> Result<(), CompressError> {
     let mut settings = get_settings_data(&app);
     let profile_idx = settings.profiles.iter().position(|p| p.id == profile_id);
     if profile_idx.is_none() {
-        return Err("Profile not found".to_string());
+        return Err(CompressError::new(
+            CompressErrorType::Invalid,
+            "Profile not found",
+        ));
     }
     let profile = settings.profiles[profile_idx.unwrap()].clone();
     settings.profiles[profile_idx.unwrap()] =
@@ -124,9 +392,12 @@ pub async fn reset_profile(app: tauri::AppHandle, profile_id: u32) -> Result<(),
 
 #[tauri::command]
 #[specta::specta]
-pub async fn delete_profile(app: tauri::AppHandle, profile_id: u32) -> Result<(), String> {
+pub async fn delete_profile(app: tauri::AppHandle, profile_id: u32) -> Result<(), CompressError> {
     if profile_id == 0 {
-        return Err("Cannot delete default profile".to_string());
+        return Err(CompressError::new(
+            CompressErrorType::Invalid,
+            "Cannot delete default profile",
+        ));
     }
     let mut settings = get_settings_data(&app);
     settings
@@ -140,7 +411,7 @@ pub async fn delete_profile(app: tauri::AppHandle, profile_id: u32) -> Result<()
 
 #[tauri::command]
 #[specta::specta]
-pub async fn add_profile(app: tauri::AppHandle, mut name: String) -> Result<(), String> {
+pub async fn add_profile(app: tauri::AppHandle, mut name: String) -> Result<(), CompressError> {
     let mut settings = get_settings_data(&app);
     let profile_idx = settings.profiles.iter().position(|p| p.name == name);
     if profile_idx.is_some() {
@@ -182,3 +453,20 @@ fn set_settings_data(app: &tauri::AppHandle, settings: SettingsData) {
     );
     emit_settings_changed(app);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_fields_match_new() {
+        let mut settings = serde_json::to_value(SettingsData::new()).unwrap();
+        settings.as_object_mut().unwrap().remove("time_limit");
+        let profile = settings["profiles"][0].as_object_mut().unwrap();
+        profile.remove("reduce_pixel_format");
+
+        let settings: SettingsData = serde_json::from_value(settings).unwrap();
+        assert_eq!(settings.time_limit, SettingsData::new().time_limit);
+        assert!(settings.profiles[0].reduce_pixel_format);
+    }
+}
@@ -0,0 +1,592 @@
+use crate::compress::{create_csparameters, resize_image, ImageType};
+use crate::error::Error;
+use crate::limits::{check_dimensions, check_file_size, tiff_limits};
+use crate::reduce::{reduce_for_output, PixelReduction};
+use crate::settings::{DecodeLimits, ProfileData};
+use ::tiff::decoder::{Decoder, DecodingResult};
+use ::tiff::encoder::colortype::{Gray16, Gray8, RGB16, RGB8, RGBA16, RGBA8};
+use ::tiff::encoder::compression::{
+    Compression, Deflate, DeflateLevel, Lzw, Packbits, Uncompressed,
+};
+use ::tiff::encoder::TiffEncoder;
+use ::tiff::tags::{PhotometricInterpretation, Tag};
+use ::tiff::{ColorType, TiffError, TiffUnsupportedError};
+use caesium::parameters::{CSParameters, TiffCompression, TiffDeflateLevel};
+use image::{DynamicImage, ImageBuffer};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, Write};
+
+const PHOTOMETRIC_TAG: u16 = 262;
+const PHOTOMETRIC_BLACK_IS_ZERO: u16 = 1;
+const PHOTOMETRIC_PALETTE: u16 = 3;
+const COMPRESSION_JPEG: u16 = 7;
+
+// Recompresses every page of a TIFF, keeping the page order
+pub fn compress_tiff(
+    path: &str,
+    out_path: &str,
+    parameters: &ProfileData,
+    limits: &DecodeLimits,
+    reductions: &mut Vec<PixelReduction>,
+) -> Result<(), Error> {
+    let mut encoder = create_encoder(out_path)?;
+    for_each_page(path, limits, |_, page| {
+        let params = create_csparameters(parameters, page.width(), page.height(), false);
+        let page = resize_image(page, params.width, params.height);
+        let page = reduce_for_output(page, ImageType::TIFF, parameters, reductions);
+        write_page(&mut encoder, &page, &params).map_err(|e| Error::encode(out_path, e))
+    })
+}
+
+pub fn encode_tiff(
+    out_path: &str,
+    image: DynamicImage,
+    params: &CSParameters,
+) -> Result<(), Error> {
+    let image = resize_image(image, params.width, params.height);
+    let mut encoder = create_encoder(out_path)?;
+    write_page(&mut encoder, &image, params).map_err(|e| Error::encode(out_path, e))
+}
+
+// `path` is only used in errors
+pub fn encode_tiff_in_memory(
+    path: &str,
+    image: DynamicImage,
+    params: &CSParameters,
+) -> Result<Vec<u8>, Error> {
+    let image = resize_image(image, params.width, params.height);
+    let mut tiff = Cursor::new(vec![]);
+    let mut encoder = TiffEncoder::new(&mut tiff).map_err(|e| Error::encode(path, e))?;
+    write_page(&mut encoder, &image, params).map_err(|e| Error::encode(path, e))?;
+    Ok(tiff.into_inner())
+}
+
+fn create_encoder(out_path: &str) -> Result<TiffEncoder<BufWriter<File>>, Error> {
+    let file = File::create(out_path).map_err(|e| Error::io(out_path, e))?;
+    TiffEncoder::new(BufWriter::new(file)).map_err(|e| Error::encode(out_path, e))
+}
+
+pub fn count_pages(path: &str, limits: &DecodeLimits) -> Result<usize, Error> {
+    let mut decoder = open_decoder(path, limits)?;
+    let mut pages = 1;
+    while decoder.more_images() {
+        decoder.next_image().map_err(|e| Error::decode(path, e))?;
+        pages += 1;
+    }
+    Ok(pages)
+}
+
+pub fn for_each_page<F>(path: &str, limits: &DecodeLimits, mut on_page: F) -> Result<(), Error>
+where
+    F: FnMut(usize, DynamicImage) -> Result<(), Error>,
+{
+    let mut decoder = open_decoder(path, limits)?;
+    let mut index = 0;
+    loop {
+        relabel_if_palette(path, &mut decoder, limits, index)?;
+        let page = read_page(path, &mut decoder, limits)?;
+        on_page(index, page)?;
+        if !decoder.more_images() {
+            return Ok(());
+        }
+        decoder.next_image().map_err(|e| Error::decode(path, e))?;
+        index += 1;
+    }
+}
+
+// The first page, for files the image crate can't decode
+pub fn read_first_page(path: &str, limits: &DecodeLimits) -> Result<DynamicImage, Error> {
+    let mut decoder = open_decoder(path, limits)?;
+    relabel_if_palette(path, &mut decoder, limits, 0)?;
+    read_page(path, &mut decoder, limits)
+}
+
+pub struct TiffHeader {
+    pub width: u32,
+    pub height: u32,
+    // As stored, `Palette` for palette pages
+    pub color_type: ColorType,
+}
+
+impl TiffHeader {
+    // Once decoded, palette pages are expanded to RGB
+    pub fn bytes_per_pixel(&self) -> u64 {
+        match self.color_type {
+            ColorType::Palette(_) => 3,
+            color_type => bytes_per_pixel(color_type),
+        }
+    }
+}
+
+// The first page's header, without its pixels
+pub fn probe_tiff(path: &str) -> Result<TiffHeader, Error> {
+    let limits = DecodeLimits::default();
+    let mut decoder = open_decoder(path, &limits)?;
+    relabel_if_palette(path, &mut decoder, &limits, 0)?;
+    let (width, height) = decoder.dimensions().map_err(|e| Error::decode(path, e))?;
+    let color_type = match (decoder.colortype(), palette(&mut decoder)) {
+        (Ok(ColorType::Gray(bits)), Some(_)) => ColorType::Palette(bits),
+        (color_type, _) => color_type.map_err(|e| Error::decode(path, e))?,
+    };
+    Ok(TiffHeader {
+        width,
+        height,
+        color_type,
+    })
+}
+
+trait Source: Read + Seek {}
+
+impl<T: Read + Seek> Source for T {}
+
+// Streamed from the file, or from memory once palette pages had to be relabelled
+type TiffDecoder = Decoder<Box<dyn Source>>;
+
+fn open_decoder(path: &str, limits: &DecodeLimits) -> Result<TiffDecoder, Error> {
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    new_decoder(path, limits, Box::new(BufReader::new(file)))
+}
+
+fn new_decoder(
+    path: &str,
+    limits: &DecodeLimits,
+    source: Box<dyn Source>,
+) -> Result<TiffDecoder, Error> {
+    Decoder::new(source)
+        .map(|decoder| decoder.with_limits(tiff_limits(limits)))
+        .map_err(|e| Error::decode(path, e))
+}
+
+// Only palette pages need the whole file in memory, see `relabel_palettes`
+fn relabel_if_palette(
+    path: &str,
+    decoder: &mut TiffDecoder,
+    limits: &DecodeLimits,
+    page: usize,
+) -> Result<(), Error> {
+    if !matches!(
+        decoder.colortype(),
+        Err(TiffError::UnsupportedError(
+            TiffUnsupportedError::InterpretationWithBits(PhotometricInterpretation::RGBPalette, _)
+        ))
+    ) {
+        return Ok(());
+    }
+    let size = fs::metadata(path).map_err(|e| Error::io(path, e))?.len();
+    check_file_size(path, limits, size)?;
+    let mut data = fs::read(path).map_err(|e| Error::io(path, e))?;
+    relabel_palettes(&mut data);
+    *decoder = new_decoder(path, limits, Box::new(Cursor::new(data)))?;
+    decoder
+        .seek_to_image(page)
+        .map_err(|e| Error::decode(path, e))
+}
+
+// The tiff crate rejects palette pages but reads their indices fine as grayscale,
+// so their photometric tag is rewritten. The color map stays to look them up.
+// BigTIFF files are left alone.
+fn relabel_palettes(data: &mut [u8]) {
+    let little_endian = match data.get(0..4) {
+        Some(b"II*\0") => true,
+        Some(b"MM\0*") => false,
+        _ => return,
+    };
+    let u16_at = |data: &[u8], pos: usize| -> Option<u16> {
+        let bytes = data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(match little_endian {
+            true => u16::from_le_bytes(bytes),
+            false => u16::from_be_bytes(bytes),
+        })
+    };
+    let u32_at = |data: &[u8], pos: usize| -> Option<usize> {
+        let bytes = data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(match little_endian {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes),
+        } as usize)
+    };
+    let mut visited = vec![];
+    let mut ifd = u32_at(data, 4);
+    // A loop in the IFD chain is stopped by `visited`
+    while let Some(offset) = ifd.filter(|&o| o != 0 && !visited.contains(&o)) {
+        visited.push(offset);
+        let Some(count) = u16_at(data, offset) else {
+            return;
+        };
+        for i in 0..count as usize {
+            let entry = offset + 2 + i * 12;
+            if u16_at(data, entry) == Some(PHOTOMETRIC_TAG)
+                && u16_at(data, entry + 8) == Some(PHOTOMETRIC_PALETTE)
+            {
+                let value = match little_endian {
+                    true => PHOTOMETRIC_BLACK_IS_ZERO.to_le_bytes(),
+                    false => PHOTOMETRIC_BLACK_IS_ZERO.to_be_bytes(),
+                };
+                data[entry + 8..entry + 10].copy_from_slice(&value);
+            }
+        }
+        ifd = u32_at(data, offset + 2 + count as usize * 12);
+    }
+}
+
+// Red, green and blue tables of 16-bit values, only on relabelled palette pages
+fn palette(decoder: &mut TiffDecoder) -> Option<Vec<u16>> {
+    decoder.find_tag_unsigned_vec(Tag::ColorMap).ok().flatten()
+}
+
+fn read_page(
+    path: &str,
+    decoder: &mut TiffDecoder,
+    limits: &DecodeLimits,
+) -> Result<DynamicImage, Error> {
+    let (width, height) = decoder.dimensions().map_err(|e| Error::decode(path, e))?;
+    let color_type = decoder.colortype().map_err(|e| Error::decode(path, e))?;
+    let palette = palette(decoder);
+    let compression = decoder
+        .find_tag_unsigned::<u16>(Tag::Compression)
+        .ok()
+        .flatten();
+    let bytes_per_pixel = match palette {
+        Some(_) => 3,
+        None => bytes_per_pixel(color_type),
+    };
+    check_dimensions(path, limits, width, height, bytes_per_pixel)?;
+    let data = decoder.read_image().map_err(|e| Error::decode(path, e))?;
+    let image = match (data, color_type) {
+        (DecodingResult::U8(buf), ColorType::Gray(bits)) if bits <= 8 && palette.is_some() => {
+            let indices = unpack_samples(&buf, width, height, bits);
+            let colors = palette.unwrap_or_default();
+            let colors = colors.as_slice();
+            let count = colors.len() / 3;
+            let rgb = indices
+                .iter()
+                .flat_map(|&i| {
+                    let i = i as usize;
+                    (0..3)
+                        .map(move |c| (colors.get(c * count + i).copied().unwrap_or(0) >> 8) as u8)
+                })
+                .collect();
+            ImageBuffer::from_raw(width, height, rgb).map(DynamicImage::ImageRgb8)
+        }
+        // Bilevel and other packed grays, scaled up to 8 bits
+        (DecodingResult::U8(buf), ColorType::Gray(bits)) if bits < 8 => {
+            let max = (1u16 << bits) - 1;
+            let gray = unpack_samples(&buf, width, height, bits)
+                .into_iter()
+                .map(|v| (v as u16 * 255 / max) as u8)
+                .collect();
+            ImageBuffer::from_raw(width, height, gray).map(DynamicImage::ImageLuma8)
+        }
+        (DecodingResult::U8(buf), ColorType::Gray(8)) => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLuma8)
+        }
+        (DecodingResult::U8(buf), ColorType::GrayA(8)) => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLumaA8)
+        }
+        (DecodingResult::U8(buf), ColorType::RGB(8)) => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgb8)
+        }
+        // Decoded to RGB by the JPEG decoder, other YCbCr pages may be subsampled
+        (DecodingResult::U8(buf), ColorType::YCbCr(8)) if compression == Some(COMPRESSION_JPEG) => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgb8)
+        }
+        (DecodingResult::U8(buf), ColorType::RGBA(8)) => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgba8)
+        }
+        (DecodingResult::U8(buf), ColorType::CMYK(8)) => {
+            let rgb = buf
+                .chunks_exact(4)
+                .flat_map(|cmyk| {
+                    let k = 255 - cmyk[3] as u16;
+                    cmyk[..3]
+                        .iter()
+                        .map(move |&c| ((255 - c as u16) * k / 255) as u8)
+                })
+                .collect();
+            ImageBuffer::from_raw(width, height, rgb).map(DynamicImage::ImageRgb8)
+        }
+        (DecodingResult::U16(buf), ColorType::Gray(16)) => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLuma16)
+        }
+        (DecodingResult::U16(buf), ColorType::GrayA(16)) => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLumaA16)
+        }
+        (DecodingResult::U16(buf), ColorType::RGB(16)) => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgb16)
+        }
+        (DecodingResult::U16(buf), ColorType::RGBA(16)) => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgba16)
+        }
+        _ => {
+            return Err(Error::unsupported(
+                path,
+                format!("Unsupported TIFF color type ({:?})", color_type),
+            ))
+        }
+    };
+    image.ok_or_else(|| Error::decode(path, "TIFF page data does not match its dimensions"))
+}
+
+// One byte per sample, rows of packed samples start on a new byte
+fn unpack_samples(buf: &[u8], width: u32, height: u32, bits: u8) -> Vec<u8> {
+    if bits == 8 {
+        return buf.to_vec();
+    }
+    let bits = bits as usize;
+    let row_bytes = (width as usize * bits).div_ceil(8);
+    let mask = ((1u16 << bits) - 1) as u8;
+    let mut samples = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let bit = x * bits;
+            let byte = buf.get(y * row_bytes + bit / 8).copied().unwrap_or(0);
+            samples.push((byte >> (8 - bits - bit % 8)) & mask);
+        }
+    }
+    samples
+}
+
+fn bytes_per_pixel(color_type: ColorType) -> u64 {
+    let bits = match color_type {
+        ColorType::Gray(bits) | ColorType::Palette(bits) => bits as u64,
+        ColorType::GrayA(bits) => bits as u64 * 2,
+        ColorType::RGB(bits) | ColorType::YCbCr(bits) => bits as u64 * 3,
+        ColorType::RGBA(bits) | ColorType::CMYK(bits) => bits as u64 * 4,
+    };
+    bits.div_ceil(8)
+}
+
+fn write_page<W: Write + Seek>(
+    encoder: &mut TiffEncoder<W>,
+    image: &DynamicImage,
+    params: &CSParameters,
+) -> ::tiff::TiffResult<()> {
+    match params.tiff.algorithm {
+        TiffCompression::Uncompressed => write_with(encoder, image, Uncompressed),
+        TiffCompression::Lzw => write_with(encoder, image, Lzw),
+        TiffCompression::Deflate => {
+            let level = match params.tiff.deflate_level {
+                TiffDeflateLevel::Fast => DeflateLevel::Fast,
+                TiffDeflateLevel::Balanced => DeflateLevel::Balanced,
+                TiffDeflateLevel::Best => DeflateLevel::Best,
+            };
+            write_with(encoder, image, Deflate::with_level(level))
+        }
+        TiffCompression::Packbits => write_with(encoder, image, Packbits),
+    }
+}
+
+fn write_with<W: Write + Seek, D: Compression>(
+    encoder: &mut TiffEncoder<W>,
+    image: &DynamicImage,
+    c: D,
+) -> ::tiff::TiffResult<()> {
+    let (w, h) = (image.width(), image.height());
+    match image {
+        DynamicImage::ImageLuma8(i) => encoder.write_image_with_compression::<Gray8, D>(w, h, c, i),
+        DynamicImage::ImageRgb8(i) => encoder.write_image_with_compression::<RGB8, D>(w, h, c, i),
+        DynamicImage::ImageRgba8(i) => encoder.write_image_with_compression::<RGBA8, D>(w, h, c, i),
+        DynamicImage::ImageLuma16(i) => {
+            encoder.write_image_with_compression::<Gray16, D>(w, h, c, i)
+        }
+        DynamicImage::ImageRgb16(i) => encoder.write_image_with_compression::<RGB16, D>(w, h, c, i),
+        DynamicImage::ImageRgba16(i) => {
+            encoder.write_image_with_compression::<RGBA16, D>(w, h, c, i)
+        }
+        // No gray + alpha in the encoder
+        DynamicImage::ImageLumaA16(_) => {
+            let i = image.to_rgba16();
+            encoder.write_image_with_compression::<RGBA16, D>(w, h, c, &i)
+        }
+        _ => {
+            let i = image.to_rgba8();
+            encoder.write_image_with_compression::<RGBA8, D>(w, h, c, &i)
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::settings;
+
+    #[test]
+    fn test_multi_page_roundtrip() {
+        let dir = std::env::temp_dir();
+        let input = dir
+            .join("alic_multi_page.tiff")
+            .to_string_lossy()
+            .to_string();
+        let output = dir
+            .join("alic_multi_page.out.tiff")
+            .to_string_lossy()
+            .to_string();
+
+        let params = CSParameters::new();
+        {
+            let file = File::create(&input).unwrap();
+            let mut encoder = TiffEncoder::new(BufWriter::new(file)).unwrap();
+            write_page(&mut encoder, &DynamicImage::new_rgb8(20, 10), &params).unwrap();
+            write_page(&mut encoder, &DynamicImage::new_luma8(30, 15), &params).unwrap();
+        }
+        let limits = DecodeLimits::default();
+        assert_eq!(count_pages(&input, &limits).unwrap(), 2);
+
+        let mut parameters = settings::ProfileData::new();
+        parameters.tiff.compression = settings::TiffCompression::Lzw;
+        compress_tiff(&input, &output, &parameters, &limits, &mut vec![]).unwrap();
+
+        let mut sizes = vec![];
+        for_each_page(&output, &limits, |_, page| {
+            sizes.push((page.width(), page.height()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(sizes, vec![(20, 10), (30, 15)]);
+
+        let _ = std::fs::remove_file(input);
+        let _ = std::fs::remove_file(output);
+    }
+
+    // One uncompressed strip, little endian
+    pub(crate) fn tiff_bytes(
+        width: u16,
+        height: u16,
+        bits: u16,
+        photometric: u16,
+        data: &[u8],
+        colors: &[u16],
+    ) -> Vec<u8> {
+        let mut bytes = b"II*\0".to_vec();
+        let colors_offset = 8 + data.len() as u32;
+        let ifd_offset = colors_offset + colors.len() as u32 * 2;
+        bytes.extend(ifd_offset.to_le_bytes());
+        bytes.extend(data);
+        bytes.extend(colors.iter().flat_map(|c| c.to_le_bytes()));
+        let mut entries: Vec<(u16, u16, u32, u32)> = vec![
+            (256, 3, 1, width as u32),
+            (257, 3, 1, height as u32),
+            (258, 3, 1, bits as u32),
+            (259, 3, 1, 1),
+            (262, 3, 1, photometric as u32),
+            (273, 4, 1, 8),
+            (277, 3, 1, 1),
+            (278, 3, 1, height as u32),
+            (279, 4, 1, data.len() as u32),
+        ];
+        if !colors.is_empty() {
+            entries.push((320, 3, colors.len() as u32, colors_offset));
+        }
+        bytes.extend((entries.len() as u16).to_le_bytes());
+        for (tag, kind, count, value) in entries {
+            bytes.extend(tag.to_le_bytes());
+            bytes.extend(kind.to_le_bytes());
+            bytes.extend(count.to_le_bytes());
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(0u32.to_le_bytes());
+        bytes
+    }
+
+    fn read_single_page(name: &str, bytes: &[u8]) -> DynamicImage {
+        let path = std::env::temp_dir()
+            .join(name)
+            .to_string_lossy()
+            .to_string();
+        std::fs::write(&path, bytes).unwrap();
+        let limits = DecodeLimits::default();
+        let image = crate::compress::read_image(&path, &limits).unwrap();
+        let header = probe_tiff(&path).unwrap();
+        assert_eq!(
+            (header.width, header.height, header.bytes_per_pixel()),
+            (
+                image.width(),
+                image.height(),
+                image.color().bytes_per_pixel() as u64
+            )
+        );
+        let _ = std::fs::remove_file(path);
+        image
+    }
+
+    #[test]
+    fn test_read_bilevel() {
+        // 10 pixels wide, so each row is padded to two bytes
+        let data = [0b1010_0000, 0b0100_0000, 0b0000_0000, 0b1100_0000];
+        let image = read_single_page("alic_bilevel.tiff", &tiff_bytes(10, 2, 1, 1, &data, &[]));
+        let image = image.as_luma8().unwrap();
+        assert_eq!(image.get_pixel(0, 0)[0], 255);
+        assert_eq!(image.get_pixel(1, 0)[0], 0);
+        assert_eq!(image.get_pixel(9, 0)[0], 255);
+        assert_eq!(image.get_pixel(0, 1)[0], 0);
+        assert_eq!(image.get_pixel(8, 1)[0], 255);
+
+        // White is zero
+        let image = read_single_page(
+            "alic_bilevel_wiz.tiff",
+            &tiff_bytes(10, 2, 1, 0, &data, &[]),
+        );
+        assert_eq!(image.as_luma8().unwrap().get_pixel(0, 0)[0], 0);
+    }
+
+    #[test]
+    fn test_read_palette() {
+        // 2 bits per index, 4 colors: black, red, green, blue
+        let colors = [0, 0xFFFF, 0, 0, 0, 0, 0xFFFF, 0, 0, 0, 0, 0xFFFF];
+        let data = [0b0001_1011, 0b1110_0100];
+        let image = read_single_page("alic_palette.tiff", &tiff_bytes(4, 2, 2, 3, &data, &colors));
+        let image = image.as_rgb8().unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(3, 0).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 1).0, [0, 0, 255]);
+
+        // Recompressed like any other TIFF
+        let dir = std::env::temp_dir();
+        let input = dir
+            .join("alic_palette_in.tiff")
+            .to_string_lossy()
+            .to_string();
+        let output = dir
+            .join("alic_palette_out.tiff")
+            .to_string_lossy()
+            .to_string();
+        std::fs::write(&input, tiff_bytes(4, 2, 2, 3, &data, &colors)).unwrap();
+        let limits = DecodeLimits::default();
+        compress_tiff(
+            &input,
+            &output,
+            &settings::ProfileData::new(),
+            &limits,
+            &mut vec![],
+        )
+        .unwrap();
+        let page = read_first_page(&output, &limits).unwrap();
+        assert_eq!(page.to_rgb8().get_pixel(1, 0).0, [255, 0, 0]);
+        let _ = std::fs::remove_file(input);
+        let _ = std::fs::remove_file(output);
+    }
+
+    #[test]
+    fn test_palette_in_memory_limit() {
+        // Only palette pages are read into memory, so only they hit the file size limit
+        let limits = DecodeLimits {
+            max_file_mb: 1,
+            ..DecodeLimits::default()
+        };
+        let colors = [0, 0xFFFF, 0, 0, 0, 0, 0xFFFF, 0, 0, 0, 0, 0xFFFF];
+        let padding = vec![0; 2 * 1024 * 1024];
+        let path = std::env::temp_dir().join("alic_palette_large.tiff");
+        let mut bytes = tiff_bytes(4, 2, 2, 3, &[0b0001_1011, 0b1110_0100], &colors);
+        bytes.extend(&padding);
+        std::fs::write(&path, bytes).unwrap();
+        let err = read_first_page(path.to_str().unwrap(), &limits).unwrap_err();
+        assert!(matches!(err, Error::LimitsExceeded { .. }));
+
+        let mut bytes = tiff_bytes(10, 2, 1, 1, &[0; 4], &[]);
+        bytes.extend(&padding);
+        std::fs::write(&path, bytes).unwrap();
+        assert!(read_first_page(path.to_str().unwrap(), &limits).is_ok());
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::compress::{guess_image_type, read_image, ImageType};
+use crate::error::Error;
+use crate::settings::DecodeLimits;
+use image::DynamicImage;
+
+// Re-opens an encoded file and checks it before it replaces anything.
+// `dimensions` lists every acceptable size, JPEGs may come out rotated.
+// `source` is only given for lossless modes, the pixels must then match.
+pub fn verify_output(
+    path: &str,
+    image_type: ImageType,
+    dimensions: &[(u32, u32)],
+    source: Option<&DynamicImage>,
+    limits: &DecodeLimits,
+) -> Result<(), Error> {
+    let actual_type =
+        guess_image_type(path).map_err(|e| Error::verification(path, e.to_string()))?;
+    if actual_type != image_type {
+        return Err(Error::verification(
+            path,
+            format!("Output is {:?}, expected {:?}", actual_type, image_type),
+        ));
+    }
+    let output = read_image(path, limits)
+        .map_err(|e| Error::verification(path, format!("Output is unreadable: {}", e)))?;
+    let (width, height) = (output.width(), output.height());
+    // libcaesium rounds resized dimensions slightly differently
+    if !dimensions
+        .iter()
+        .any(|&(w, h)| w.abs_diff(width) <= 1 && h.abs_diff(height) <= 1)
+    {
+        return Err(Error::verification(
+            path,
+            format!(
+                "Output is {}x{}, expected {}x{}",
+                width, height, dimensions[0].0, dimensions[0].1
+            ),
+        ));
+    }
+    if let Some(source) = source {
+        if !pixels_match(source, &output) {
+            return Err(Error::verification(
+                path,
+                "Output pixels differ from the original",
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Compared at the output's precision, colours under fully transparent pixels don't count
+fn pixels_match(source: &DynamicImage, output: &DynamicImage) -> bool {
+    if source.width() != output.width() || source.height() != output.height() {
+        return false;
+    }
+    let sixteen_bit = matches!(
+        output,
+        DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_)
+    );
+    if sixteen_bit {
+        let (a, b) = (source.to_rgba16(), output.to_rgba16());
+        a.pixels()
+            .zip(b.pixels())
+            .all(|(a, b)| a == b || (a[3] == 0 && b[3] == 0))
+    } else {
+        let (a, b) = (source.to_rgba8(), output.to_rgba8());
+        a.pixels()
+            .zip(b.pixels())
+            .all(|(a, b)| a == b || (a[3] == 0 && b[3] == 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_output() {
+        let path = std::env::temp_dir()
+            .join("alic_verify.png")
+            .to_string_lossy()
+            .to_string();
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(8, 4, |x, y| {
+            image::Rgba([x as u8, y as u8, 0, 255])
+        }));
+        image.save(&path).unwrap();
+        let limits = DecodeLimits::default();
+
+        assert!(verify_output(&path, ImageType::PNG, &[(8, 4)], Some(&image), &limits).is_ok());
+        assert!(verify_output(&path, ImageType::JPEG, &[(8, 4)], None, &limits).is_err());
+        assert!(verify_output(&path, ImageType::PNG, &[(16, 8)], None, &limits).is_err());
+        let other = DynamicImage::new_rgba8(8, 4);
+        assert!(verify_output(&path, ImageType::PNG, &[(8, 4)], Some(&other), &limits).is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_pixels_match_ignores_transparent_colour() {
+        let a = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            2,
+            2,
+            image::Rgba([9, 9, 9, 0]),
+        ));
+        let b = DynamicImage::new_rgba8(2, 2);
+        assert!(pixels_match(&a, &b));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::compress::resize_image;
+use crate::error::Error;
+use crate::limits::check_dimensions;
+use crate::settings::{DecodeLimits, WebpMode, WebpOptions};
+use ::webp::{AnimDecoder, AnimEncoder, AnimFrame, Encoder, WebPConfig};
+use caesium::parameters::CSParameters;
+use image::codecs::webp::WebPDecoder;
+use image::DynamicImage;
+use std::fs;
+use std::io::BufReader;
+
+pub fn compress_webp(
+    out_path: &str,
+    image: DynamicImage,
+    params: &CSParameters,
+    options: &WebpOptions,
+) -> Result<(), Error> {
+    let webp = compress_webp_in_memory(out_path, image, params, options)?;
+    fs::write(out_path, webp).map_err(|e| Error::io(out_path, e))
+}
+
+// `path` is only used in errors
+pub fn compress_webp_in_memory(
+    path: &str,
+    image: DynamicImage,
+    params: &CSParameters,
+    options: &WebpOptions,
+) -> Result<Vec<u8>, Error> {
+    let image = resize_image(image, params.width, params.height);
+    let config = create_webp_config(path, params.webp.quality, options)?;
+
+    let encoded = if image.color().has_alpha() {
+        let rgba = image.to_rgba8();
+        Encoder::from_rgba(&rgba, rgba.width(), rgba.height()).encode_advanced(&config)
+    } else {
+        let rgb = image.to_rgb8();
+        Encoder::from_rgb(&rgb, rgb.width(), rgb.height()).encode_advanced(&config)
+    }
+    .map_err(|e| Error::encode(path, format!("{:?}", e)))?;
+
+    Ok(encoded.to_vec())
+}
+
+pub fn compress_animated_webp(
+    path: &str,
+    out_path: &str,
+    params: &CSParameters,
+    options: &WebpOptions,
+    limits: &DecodeLimits,
+) -> Result<(), Error> {
+    let webp = compress_animated_webp_in_memory(path, params, options, limits)?;
+    fs::write(out_path, webp).map_err(|e| Error::io(out_path, e))
+}
+
+// Same options as a still image, applied to every frame
+pub fn compress_animated_webp_in_memory(
+    path: &str,
+    params: &CSParameters,
+    options: &WebpOptions,
+    limits: &DecodeLimits,
+) -> Result<Vec<u8>, Error> {
+    let data = fs::read(path).map_err(|e| Error::io(path, e))?;
+    let (width, height) =
+        canvas_size(&data).ok_or_else(|| Error::decode(path, "Missing VP8X header"))?;
+    // Every frame is decoded up front, then copied out of the decoder
+    let frames = count_webp_frames(&data) as u64;
+    check_dimensions(path, limits, width, height, 2 * 4 * frames)?;
+    let decoded = AnimDecoder::new(&data)
+        .decode()
+        .map_err(|e| Error::decode(path, e))?;
+    let mut images = vec![];
+    for frame in &decoded {
+        let image: DynamicImage = (&frame).into();
+        let image = resize_image(image, params.width, params.height);
+        images.push((image, frame.get_time_ms()));
+    }
+    let (loop_count, bgcolor) = (decoded.loop_count, decoded.bg_color);
+    drop(decoded);
+
+    let config = create_webp_config(path, params.webp.quality, options)?;
+    let Some((first, _)) = images.first() else {
+        return Err(Error::decode(path, "No frames"));
+    };
+    let mut encoder = AnimEncoder::new(first.width(), first.height(), &config);
+    encoder.set_loop_count(loop_count as i32);
+    // Stored as it was read, set_bgcolor packs the bytes back the same way
+    encoder.set_bgcolor(bgcolor.to_le_bytes());
+    // Decoded timestamps are when a frame ends, the encoder wants when it starts.
+    // libwebp gives the last frame the average duration.
+    let mut start = 0;
+    for (image, end) in &images {
+        let frame = AnimFrame::from_image(image, start).map_err(|e| Error::encode(path, e))?;
+        encoder.add_frame(frame);
+        start = *end;
+    }
+    let encoded = encoder
+        .try_encode()
+        .map_err(|e| Error::encode(path, format!("{:?}", e)))?;
+    Ok(encoded.to_vec())
+}
+
+// From the VP8X chunk, which every animation has
+fn canvas_size(data: &[u8]) -> Option<(u32, u32)> {
+    if data.get(12..16)? != b"VP8X" {
+        return None;
+    }
+    let u24 = |pos: usize| -> Option<u32> {
+        let bytes = data.get(pos..pos + 3)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) + 1)
+    };
+    Some((u24(24)?, u24(27)?))
+}
+
+pub fn count_webp_frames(data: &[u8]) -> u32 {
+    let mut pos = 12;
+    let mut frames = 0;
+    while let Some(header) = data.get(pos..pos + 8) {
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        if &header[0..4] == b"ANMF" {
+            frames += 1;
+        }
+        // Chunks are padded to an even size
+        pos += 8 + size + size % 2;
+    }
+    frames.max(1)
+}
+
+// `path` is only used in errors
+fn create_webp_config(
+    path: &str,
+    quality: u32,
+    options: &WebpOptions,
+) -> Result<WebPConfig, Error> {
+    let mut config =
+        WebPConfig::new().map_err(|_| Error::encode(path, "Cannot initialize WebP config"))?;
+    let method = options.method.min(6);
+    config.method = method as i32;
+    config.alpha_quality = options.alpha_quality.min(100) as i32;
+    config.use_sharp_yuv = options.sharp_yuv as i32;
+    match options.mode {
+        WebpMode::Lossy => {
+            config.lossless = 0;
+            config.quality = quality.min(100) as f32;
+        }
+        WebpMode::Lossless => {
+            // For lossless, quality is how hard to try
+            config.lossless = 1;
+            config.quality = method as f32 / 6.0 * 100.0;
+        }
+        WebpMode::NearLossless => {
+            config.lossless = 1;
+            config.quality = method as f32 / 6.0 * 100.0;
+            config.near_lossless = quality.min(100) as i32;
+        }
+    }
+    Ok(config)
+}
+
+// Decoding with image only keeps the first frame, animations take their own path
+pub fn is_animated_webp(path: &str) -> bool {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+    match WebPDecoder::new(BufReader::new(file)) {
+        Ok(decoder) => decoder.has_animation(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_webp_config() {
+        let mut options = WebpOptions::default();
+        let mut config = create_webp_config("a.webp", 70, &options).unwrap();
+        assert_eq!(config.lossless, 0);
+        assert_eq!(config.quality, 70.0);
+        assert_eq!(config.method, 4);
+
+        options.mode = WebpMode::NearLossless;
+        options.sharp_yuv = true;
+        options.alpha_quality = 50;
+        config = create_webp_config("a.webp", 60, &options).unwrap();
+        assert_eq!(config.lossless, 1);
+        assert_eq!(config.near_lossless, 60);
+        assert_eq!(config.use_sharp_yuv, 1);
+        assert_eq!(config.alpha_quality, 50);
+    }
+
+    #[test]
+    fn test_compress_animated_webp() {
+        let config = WebPConfig::new().unwrap();
+        let mut encoder = AnimEncoder::new(16, 16, &config);
+        let frames: Vec<DynamicImage> = (0..3)
+            .map(|i| {
+                DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                    16,
+                    16,
+                    image::Rgba([i * 100, 0, 0, 255]),
+                ))
+            })
+            .collect();
+        for (frame, start) in frames.iter().zip([0, 100, 250]) {
+            encoder.add_frame(AnimFrame::from_image(frame, start).unwrap());
+        }
+        encoder.set_loop_count(3);
+        let path = std::env::temp_dir().join("alic-test-animated.webp");
+        fs::write(&path, &*encoder.encode()).unwrap();
+        let path = path.to_string_lossy();
+        assert!(is_animated_webp(&path));
+
+        let mut params = CSParameters::new();
+        params.width = 8;
+        let options = WebpOptions {
+            mode: WebpMode::Lossless,
+            ..WebpOptions::default()
+        };
+        let webp =
+            compress_animated_webp_in_memory(&path, &params, &options, &DecodeLimits::default())
+                .unwrap();
+        let decoded = AnimDecoder::new(&webp).decode().unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded.loop_count, 3);
+        let frames: Vec<AnimFrame> = decoded.into_iter().collect();
+        assert_eq!((frames[0].width(), frames[0].height()), (8, 8));
+        assert_eq!(frames[0].get_time_ms(), 100);
+        assert_eq!(frames[1].get_time_ms(), 250);
+        assert!(frames[1].get_image()[0].abs_diff(100) <= 4);
+    }
+}
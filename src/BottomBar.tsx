@@ -1,8 +1,12 @@
-import { open } from "@tauri-apps/plugin-dialog";
-import { FaSolidXmark } from "solid-icons/fa";
+import { open, save } from "@tauri-apps/plugin-dialog";
+import {
+  FaSolidFileExport,
+  FaSolidRotateLeft,
+  FaSolidXmark,
+} from "solid-icons/fa";
 import { VsAdd, VsSettings } from "solid-icons/vs";
 import { type JSXElement, Show } from "solid-js";
-import { type ProfileData, commands } from "./bindings";
+import { type ProfileData, type ReportFormat, commands } from "./bindings";
 import { FILE_TYPES } from "./constants";
 import { openFileDialogListener } from "./listeners";
 import { SettingsSelect } from "./settings/SettingsUI";
@@ -11,7 +15,15 @@ import {
   setProfileActive,
   settings,
 } from "./settings/settingsData";
-import { addFile, clearFiles, store } from "./store";
+import {
+  addFiles,
+  clearFiles,
+  discardQueue,
+  exportReport,
+  resumeQueue,
+  store,
+  undoFiles,
+} from "./store";
 import { toHumanReadableSize } from "./utils";
 
 openFileDialogListener(() => {
@@ -32,9 +44,29 @@ async function openFile() {
   if (!file) {
     return;
   }
-  for (const f of file) {
-    addFile(f);
+  addFiles(file);
+}
+
+const REPORT_FORMATS: Record<string, ReportFormat> = {
+  csv: "Csv",
+  json: "Json",
+  html: "Html",
+};
+
+async function saveReport() {
+  const path = await save({
+    defaultPath: "alic-report.html",
+    filters: [
+      { name: "HTML Report", extensions: ["html"] },
+      { name: "CSV", extensions: ["csv"] },
+      { name: "JSON", extensions: ["json"] },
+    ],
+  });
+  if (!path) {
+    return;
   }
+  const extension = path.split(".").pop()?.toLowerCase() ?? "";
+  exportReport(REPORT_FORMATS[extension] ?? "Html", path);
 }
 
 export default function BottomBar() {
@@ -76,6 +108,8 @@ export default function BottomBar() {
         options={options().map((e) => e.label)}
       />
       <SettingsButton />
+      <ExportButton />
+      <UndoButton />
       <ClearButton />
     </div>
   );
@@ -91,6 +125,27 @@ function AddButton() {
   );
 }
 
+function ExportButton() {
+  return (
+    <Button onClick={saveReport} disabled={store.files.length === 0}>
+      <span class="flex items-center gap-1 px-2 text-sm">
+        <FaSolidFileExport /> Report
+      </span>
+    </Button>
+  );
+}
+
+function UndoButton() {
+  const anyComplete = () => store.files.some((f) => f.status === "Complete");
+  return (
+    <Button onClick={undoFiles} disabled={!anyComplete()}>
+      <span class="flex items-center gap-1 px-2 text-sm">
+        <FaSolidRotateLeft /> Undo
+      </span>
+    </Button>
+  );
+}
+
 function ClearButton() {
   return (
     <Button onClick={clearFiles} disabled={store.files.length === 0}>
@@ -106,6 +161,41 @@ async function settingsWindow() {
 }
 
 function StatusText() {
+  const unfinished = () =>
+    store.resumable.filter(
+      (f) => f.status === "Processing" || f.status === "Compressing",
+    ).length;
+  return (
+    <Show when={unfinished() > 0} fallback={<ScanText />}>
+      {unfinished()} images left from last time
+      <Button class="ml-2" onClick={resumeQueue}>
+        <span class="px-2 text-sm">Resume</span>
+      </Button>
+      <Button onClick={discardQueue}>
+        <span class="px-2 text-sm">Discard</span>
+      </Button>
+    </Show>
+  );
+}
+
+function ScanText() {
+  const scans = () =>
+    Object.values(store.scans).filter((s) => s !== undefined);
+  const scanFound = () => scans().reduce((a, s) => a + s.found, 0);
+  return (
+    <Show when={scans().length > 0} fallback={<SavingsText />}>
+      Scanning, {scanFound()} images found
+      <Show when={store.scanErrors.length > 0}>
+        , {store.scanErrors.length} folders unreadable
+      </Show>
+      <Button class="ml-2" onClick={() => commands.cancelScan()}>
+        <span class="px-2 text-sm">Stop</span>
+      </Button>
+    </Show>
+  );
+}
+
+function SavingsText() {
   const doneFiles = () => store.files.filter((f) => f.status === "Complete");
   const dataSaved = () =>
     doneFiles()
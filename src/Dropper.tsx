@@ -4,13 +4,17 @@ import { BsArrowDownSquare } from "solid-icons/bs";
 import { Show, createSignal, onCleanup } from "solid-js";
 import { Transition } from "solid-transition-group";
 import { commands } from "./bindings";
-import { addFileListener } from "./listeners";
-import { addFile } from "./store";
+import { addFileListener, addFilesListener } from "./listeners";
+import { addFile, addFiles } from "./store";
 
 addFileListener((path) => {
   addFile(path);
 });
 
+addFilesListener((paths) => {
+  addFiles(paths);
+});
+
 export default function Dropper() {
   const [showDropper, setShowDropper] = createSignal(false);
   const cancel = getCurrentWebview().onDragDropEvent(
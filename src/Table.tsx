@@ -1,6 +1,11 @@
 import Tooltip from "@corvu/tooltip";
 import { BsArrowDown, BsArrowDownSquare, BsArrowUp } from "solid-icons/bs";
-import { FaSolidCircleNotch, FaSolidMinus, FaSolidXmark } from "solid-icons/fa";
+import {
+  FaSolidCircleNotch,
+  FaSolidForward,
+  FaSolidMinus,
+  FaSolidXmark,
+} from "solid-icons/fa";
 import { FaSolidCheck } from "solid-icons/fa";
 import { TbDots } from "solid-icons/tb";
 import { For, type JSXElement, Match, Switch, splitProps } from "solid-js";
@@ -19,6 +24,7 @@ const statusOrder: Array<FileEntryStatus> = [
   "Processing",
   "Complete",
   "AlreadySmaller",
+  "Skipped",
   "Error",
 ];
 
@@ -40,6 +46,9 @@ function StatusIcons(props: { status: FileEntryStatus }) {
       <Match when={props.status === "AlreadySmaller"}>
         <FaSolidMinus />
       </Match>
+      <Match when={props.status === "Skipped"}>
+        <FaSolidForward />
+      </Match>
     </Switch>
   );
 }
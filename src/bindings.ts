@@ -16,7 +16,7 @@ async processImg(parameters: ProfileData, file: FileEntry) : Promise<Result<Comp
     else return { status: "error", error: e  as any };
 }
 },
-async getFileInfo(path: string) : Promise<Result<FileInfoResult, string>> {
+async getFileInfo(path: string) : Promise<Result<FileInfoResult, CompressError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_file_info", { path }) };
 } catch (e) {
@@ -24,7 +24,10 @@ async getFileInfo(path: string) : Promise<Result<FileInfoResult, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async getAllImages(path: string) : Promise<Result<null, string>> {
+async waitForJob(path: string) : Promise<void> {
+    await TAURI_INVOKE("wait_for_job", { path });
+},
+async getAllImages(path: string) : Promise<Result<null, CompressError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_all_images", { path }) };
 } catch (e) {
@@ -32,7 +35,125 @@ async getAllImages(path: string) : Promise<Result<null, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async getSettings() : Promise<Result<SettingsData, string>> {
+async cancelScan() : Promise<void> {
+    await TAURI_INVOKE("cancel_scan");
+},
+async getHistory(path: string | null) : Promise<Result<HistoryEntry[], CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_history", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async clearHistory() : Promise<Result<null, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clear_history") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getJournal() : Promise<Result<JournalEntry[], CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_journal") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async newBatch() : Promise<void> {
+    await TAURI_INVOKE("new_batch");
+},
+async undoFile(path: string) : Promise<Result<number, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("undo_file", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async undoBatch(batchId: number | null) : Promise<Result<number, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("undo_batch", { batchId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async pruneBackups(days: number) : Promise<Result<number, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("prune_backups", { days }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getQueue() : Promise<Result<FileEntry[], CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_queue") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async queueFiles(files: FileEntry[]) : Promise<Result<null, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("queue_files", { files }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async unqueueFiles(paths: string[]) : Promise<Result<null, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("unqueue_files", { paths }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async clearQueue() : Promise<Result<null, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clear_queue") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async exportReport(files: FileEntry[], format: ReportFormat, path: string) : Promise<Result<null, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_report", { files, format, path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async compareImages(original: string, output: string, heatmapPath: string | null) : Promise<Result<QualityMetrics, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("compare_images", { original, output, heatmapPath }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async previewImage(path: string, parameters: ProfileData, maxSize: number) : Promise<Result<Preview, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("preview_image", { path, parameters, maxSize }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async inspectImage(path: string) : Promise<Result<ImageInfo, CompressError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("inspect_image", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getSettings() : Promise<Result<SettingsData, CompressError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_settings") };
 } catch (e) {
@@ -40,7 +161,7 @@ async getSettings() : Promise<Result<SettingsData, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async saveSettings(settings: SettingsData) : Promise<Result<null, string>> {
+async saveSettings(settings: SettingsData) : Promise<Result<null, CompressError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("save_settings", { settings }) };
 } catch (e) {
@@ -48,7 +169,7 @@ async saveSettings(settings: SettingsData) : Promise<Result<null, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async resetSettings() : Promise<Result<null, string>> {
+async resetSettings() : Promise<Result<null, CompressError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("reset_settings") };
 } catch (e) {
@@ -56,7 +177,7 @@ async resetSettings() : Promise<Result<null, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async resetProfile(profileId: number) : Promise<Result<null, string>> {
+async resetProfile(profileId: number) : Promise<Result<null, CompressError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("reset_profile", { profileId }) };
 } catch (e) {
@@ -64,7 +185,7 @@ async resetProfile(profileId: number) : Promise<Result<null, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async deleteProfile(profileId: number) : Promise<Result<null, string>> {
+async deleteProfile(profileId: number) : Promise<Result<null, CompressError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("delete_profile", { profileId }) };
 } catch (e) {
@@ -72,7 +193,7 @@ async deleteProfile(profileId: number) : Promise<Result<null, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async addProfile(name: string) : Promise<Result<null, string>> {
+async addProfile(name: string) : Promise<Result<null, CompressError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("add_profile", { name }) };
 } catch (e) {
@@ -103,16 +224,41 @@ async getCpuCount() : Promise<number> {
 
 /** user-defined types **/
 
-export type CompressError = { error: string; errorType: CompressErrorType }
-export type CompressErrorType = "Unknown" | "FileTooLarge" | "FileNotFound" | "UnsupportedFileType" | "WontOverwrite" | "NotSmaller"
-export type CompressResult = { path: string; outSize: number; outPath: string; result: string }
-export type FileEntry = { path: string; file: string | null; status: FileEntryStatus; size: number | null; originalSize: number | null; ext: string | null; savings: number | null; error: string | null }
-export type FileEntryStatus = "Processing" | "Compressing" | "Complete" | "AlreadySmaller" | "Error"
-export type FileInfoResult = { size: number; extension: string; filename: string }
+export type ChromaSubsampling = "CS444" | "CS422" | "CS420" | "Auto"
+export type CompressError = { error: string; errorType: CompressErrorType; path: string | null; causes: string[] }
+export type CompressErrorType = "Unknown" | "FileTooLarge" | "FileNotFound" | "UnsupportedFileType" | "WontOverwrite" | "NotSmaller" | "LimitsExceeded" | "Timeout" | "VerificationFailed" | "Io" | "Permission" | "DiskFull" | "Decode" | "Encode" | "Skipped" | "AlreadyProcessed" | "Invalid"
+export type CompressResult = { path: string; outSize: number; outPath: string; result: string; reductions: PixelReduction[]; bytesSaved: number; inType: ImageType; outType: ImageType; inWidth: number; inHeight: number; outWidth: number; outHeight: number; quality: number | null; metadataStripped: boolean; resized: boolean; decodeMs: number; encodeMs: number; metrics: QualityMetrics | null }
+export type DecodeLimits = { max_width: number; max_height: number; max_alloc_mb: number; max_file_mb: number }
+export type ExifSummary = { camera: string | null; date: string | null; orientation: number | null; has_gps: boolean }
+export type FileEntry = { path: string; file: string | null; status: FileEntryStatus; size: number | null; originalSize: number | null; ext: string | null; savings: number | null; error: string | null; outPath?: string | null; errorType?: CompressErrorType | null; width?: number | null; height?: number | null; imageType?: ImageType | null; outType?: ImageType | null }
+export type FileEntryStatus = "Processing" | "Compressing" | "Complete" | "AlreadySmaller" | "Skipped" | "Error"
+export type FileFilters = { min_size_kb: number; max_size_kb: number; min_width: number; max_width: number; min_height: number; max_height: number; modified_after: number; match_any: boolean }
+export type FileInfoResult = { size: number; extension: string; filename: string; peak_mb: number; width: number; height: number; image_type: ImageType | null }
+export type HistoryEntry = { input_hash: string; output_hash: string; profile_hash: string; path: string; out_path: string; input_size: number; output_size: number; timestamp: number }
+export type ImageInfo = { size: number; image_type: ImageType; width: number; height: number; color_type: string; bit_depth: number; has_alpha: boolean; frames: number; icc_profile: string | null; exif: ExifSummary | null; estimated_savings: number }
 export type ImageType = "JPEG" | "PNG" | "WEBP" | "GIF" | "TIFF"
-export type ProfileData = { name: string; id: number; active: boolean; should_resize: boolean; should_convert: boolean; should_overwrite: boolean; add_posfix?: boolean; convert_extension: ImageType; postfix: string; resize_width: number; resize_height: number; jpeg_quality: number; png_quality: number; webp_quality: number; gif_quality: number }
-export type SettingsData = { version: number; theme: ThemeKind; threads?: number; profiles: ProfileData[] }
+export type JournalAction = "Create" | "Replace" | "Trash"
+export type JournalEntry = { batch_id: number; path: string; ops: JournalOp[]; timestamp: number; undone: boolean }
+export type JournalOp = { action: JournalAction; path: string; backup: string | null }
+export type JpegOptions = { progressive: boolean; chroma_subsampling: ChromaSubsampling; text_quality_floor: number; lossless?: boolean; rotation?: JpegRotation; keep_metadata?: boolean }
+export type JpegRotation = "None" | "Auto" | "Rotate90" | "Rotate180" | "Rotate270"
+export type PixelReduction = "To8Bit" | "DropAlpha" | "Grayscale" | "Palette"
+export type PngOptions = { lossless: boolean; max_colors: number; dithering: number; optimization_level: number; interlace: boolean }
+export type Preview = { image: string; size: number; original_size: number; out_type: ImageType; width: number; height: number; metrics: QualityMetrics }
+export type ProfileData = { name: string; id: number; active: boolean; should_resize: boolean; should_convert: boolean; should_overwrite: boolean; add_posfix?: boolean; convert_extension: ImageType; postfix: string; resize_width: number; resize_height: number; jpeg_quality: number; png_quality: number; webp_quality: number; gif_quality: number; reduce_pixel_format?: boolean; jpeg?: JpegOptions; png?: PngOptions; webp?: WebpOptions; tiff?: TiffOptions; scan?: ScanOptions; filters?: FileFilters }
+export type QualityMetrics = { psnr: number | null; ssim: number; max_error: number }
+export type ReportFormat = "Csv" | "Json" | "Html"
+export type ScanError = { root: string; path: string; error: string }
+export type ScanOptions = { include: string[]; exclude: string[]; use_ignore_files: boolean; max_depth: number; skip_hidden: boolean; symlinks: SymlinkPolicy }
+export type ScanProgress = { root: string; scanned_dirs: number; found: number; errors: number; done: boolean; cancelled: boolean }
+export type SettingsData = { version: number; theme: ThemeKind; threads?: number; memory_budget?: number; limits?: DecodeLimits; time_limit?: number; include_extensionless?: boolean; recompress_processed?: boolean; backup_originals?: boolean; measure_quality?: boolean; profiles: ProfileData[] }
+export type SymlinkPolicy = "Skip" | "Follow"
 export type ThemeKind = "Light" | "Dark" | "System"
+export type TiffCompression = "None" | "Lzw" | "Deflate" | "PackBits"
+export type TiffDeflateLevel = "Fast" | "Balanced" | "Best"
+export type TiffOptions = { compression: TiffCompression; deflate_level: TiffDeflateLevel }
+export type WebpMode = "Lossy" | "Lossless" | "NearLossless"
+export type WebpOptions = { mode: WebpMode; method: number; alpha_quality: number; sharp_yuv: boolean }
 
 /** tauri-specta globals **/
 
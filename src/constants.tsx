@@ -1,3 +1,3 @@
-const FILE_TYPES = ["png", "jpeg", "jpg", "gif", "webp", "tiff"];
+const FILE_TYPES = ["png", "jpeg", "jpg", "gif", "webp", "tiff", "tif"];
 
 export { FILE_TYPES };
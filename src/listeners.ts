@@ -1,4 +1,5 @@
 import { listen } from "@tauri-apps/api/event";
+import type { ScanError, ScanProgress } from "./bindings";
 
 function openFileDialogListener(cb: () => void) {
   return listen("open-find-image-dialog", () => {
@@ -12,6 +13,24 @@ function addFileListener(cb: (path: string) => void) {
   });
 }
 
+function addFilesListener(cb: (paths: string[]) => void) {
+  return listen<string[]>("add-files", (event) => {
+    cb(event.payload);
+  });
+}
+
+function scanProgressListener(cb: (progress: ScanProgress) => void) {
+  return listen<ScanProgress>("scan-progress", (event) => {
+    cb(event.payload);
+  });
+}
+
+function scanErrorListener(cb: (error: ScanError) => void) {
+  return listen<ScanError>("scan-error", (event) => {
+    cb(event.payload);
+  });
+}
+
 function clearFilesListener(cb: () => void) {
   return listen("clear-files", (_) => {
     cb();
@@ -27,6 +46,9 @@ function settingsChangedListener(cb: () => void) {
 export {
   openFileDialogListener,
   addFileListener,
+  addFilesListener,
+  scanProgressListener,
+  scanErrorListener,
   clearFilesListener,
   settingsChangedListener,
 };
@@ -1,5 +1,20 @@
 import { useNavigate, useParams } from "@solidjs/router";
-import { type ImageType, commands } from "../bindings";
+import {
+  type ChromaSubsampling,
+  type FileFilters,
+  type ImageType,
+  type JpegOptions,
+  type JpegRotation,
+  type PngOptions,
+  type ScanOptions,
+  type SymlinkPolicy,
+  type TiffCompression,
+  type TiffDeflateLevel,
+  type TiffOptions,
+  type WebpMode,
+  type WebpOptions,
+  commands,
+} from "../bindings";
 import { confirmModal } from "./ConfirmModal";
 import {
   SettingBox,
@@ -14,6 +29,71 @@ import {
 import { deleteProfile, settings, updateProfile } from "./settingsData";
 
 const imageTypes: ImageType[] = ["JPEG", "PNG", "WEBP", "GIF", "TIFF"];
+const chromaSubsamplings: ChromaSubsampling[] = [
+  "Auto",
+  "CS444",
+  "CS422",
+  "CS420",
+];
+const defaultJpegOptions: JpegOptions = {
+  progressive: true,
+  chroma_subsampling: "Auto",
+  text_quality_floor: 0,
+  lossless: false,
+  rotation: "Auto",
+  keep_metadata: false,
+};
+const jpegRotations: JpegRotation[] = [
+  "Auto",
+  "None",
+  "Rotate90",
+  "Rotate180",
+  "Rotate270",
+];
+const defaultPngOptions: PngOptions = {
+  lossless: false,
+  max_colors: 256,
+  dithering: 100,
+  optimization_level: 3,
+  interlace: false,
+};
+const tiffCompressions: TiffCompression[] = [
+  "None",
+  "Lzw",
+  "Deflate",
+  "PackBits",
+];
+const tiffDeflateLevels: TiffDeflateLevel[] = ["Fast", "Balanced", "Best"];
+const defaultTiffOptions: TiffOptions = {
+  compression: "Deflate",
+  deflate_level: "Balanced",
+};
+const webpModes: WebpMode[] = ["Lossy", "Lossless", "NearLossless"];
+const defaultFileFilters: FileFilters = {
+  min_size_kb: 0,
+  max_size_kb: 0,
+  min_width: 0,
+  max_width: 0,
+  min_height: 0,
+  max_height: 0,
+  modified_after: 0,
+  match_any: false,
+};
+const symlinkPolicies: SymlinkPolicy[] = ["Skip", "Follow"];
+const defaultScanOptions: ScanOptions = {
+  include: [],
+  exclude: ["node_modules"],
+  use_ignore_files: true,
+  max_depth: 0,
+  skip_hidden: true,
+  symlinks: "Skip",
+};
+const defaultWebpOptions: WebpOptions = {
+  mode: "Lossy",
+  method: 4,
+  alpha_quality: 100,
+  sharp_yuv: false,
+};
 
 function ProfilePage() {
   const navigate = useNavigate();
@@ -27,6 +107,30 @@ function ProfilePage() {
     }
     return d;
   };
+  const jpeg = () => data().jpeg ?? defaultJpegOptions;
+  const updateJpeg = (update: Partial<JpegOptions>) => {
+    updateProfile(data().id, { jpeg: { ...jpeg(), ...update } });
+  };
+  const png = () => data().png ?? defaultPngOptions;
+  const updatePng = (update: Partial<PngOptions>) => {
+    updateProfile(data().id, { png: { ...png(), ...update } });
+  };
+  const webp = () => data().webp ?? defaultWebpOptions;
+  const updateWebp = (update: Partial<WebpOptions>) => {
+    updateProfile(data().id, { webp: { ...webp(), ...update } });
+  };
+  const filters = () => data().filters ?? defaultFileFilters;
+  const updateFilters = (update: Partial<FileFilters>) => {
+    updateProfile(data().id, { filters: { ...filters(), ...update } });
+  };
+  const scan = () => data().scan ?? defaultScanOptions;
+  const updateScan = (update: Partial<ScanOptions>) => {
+    updateProfile(data().id, { scan: { ...scan(), ...update } });
+  };
+  const tiff = () => data().tiff ?? defaultTiffOptions;
+  const updateTiff = (update: Partial<TiffOptions>) => {
+    updateProfile(data().id, { tiff: { ...tiff(), ...update } });
+  };
   return (
     <SettingsPage title={`Profile | ${data().name}`}>
       <SettingBox title="Quality">
@@ -62,6 +166,205 @@ function ProfilePage() {
             }}
           />
         </SettingRow>
+        <SettingRow
+          title="Reduce Pixel Format"
+          helpText="Losslessly drop unused 16-bit depth, opaque alpha and colour from PNG and TIFF images."
+        >
+          <SettingsToggle
+            value={data().reduce_pixel_format ?? false}
+            onChange={(value) =>
+              updateProfile(data().id, { reduce_pixel_format: value })
+            }
+          />
+        </SettingRow>
+      </SettingBox>
+      <div class="pt-8" />
+      <SettingBox title="JPEG">
+        <SettingRow
+          title="Progressive"
+          helpText="Progressive JPEGs load in passes and are usually smaller. Disable for baseline JPEGs."
+        >
+          <SettingsToggle
+            value={jpeg().progressive}
+            onChange={(value) => updateJpeg({ progressive: value })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Chroma Subsampling"
+          helpText="How much colour detail to keep. 4:4:4 keeps all of it, 4:2:0 is the smallest."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={jpeg().chroma_subsampling}
+            onChange={(value) =>
+              updateJpeg({ chroma_subsampling: value as ChromaSubsampling })
+            }
+            options={chromaSubsamplings}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Text Quality Floor"
+          helpText="Minimum JPEG quality for images that look like text or screenshots. Set to 0 to disable."
+        >
+          <SettingsNumberInput
+            value={jpeg().text_quality_floor}
+            onChange={(value) =>
+              updateJpeg({ text_quality_floor: Math.min(value, 100) })
+            }
+          />
+        </SettingRow>
+        <SettingRow
+          title="Lossless"
+          helpText="Optimize JPEGs without re-encoding them, so they never lose quality. Quality, subsampling and resize are ignored."
+        >
+          <SettingsToggle
+            value={jpeg().lossless ?? false}
+            onChange={(value) => updateJpeg({ lossless: value })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Lossless Rotation"
+          helpText="Rotation applied in lossless mode. Auto follows the camera orientation and resets it in the metadata."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={jpeg().rotation ?? "Auto"}
+            onChange={(value) =>
+              updateJpeg({ rotation: value as JpegRotation })
+            }
+            options={jpegRotations}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Keep Metadata"
+          helpText="Copy EXIF and other metadata in lossless mode. When off, only the colour profile and orientation are kept."
+        >
+          <SettingsToggle
+            value={jpeg().keep_metadata ?? false}
+            onChange={(value) => updateJpeg({ keep_metadata: value })}
+          />
+        </SettingRow>
+      </SettingBox>
+      <div class="pt-8" />
+      <SettingBox title="PNG">
+        <SettingRow
+          title="Lossless Only"
+          helpText="Only optimize PNGs, never reduce their colours. PNG Quality is ignored."
+        >
+          <SettingsToggle
+            value={png().lossless}
+            onChange={(value) => updatePng({ lossless: value })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Max Colors"
+          helpText="Maximum palette size when reducing colours, between 2 and 256."
+        >
+          <SettingsNumberInput
+            value={png().max_colors}
+            onChange={(value) =>
+              updatePng({ max_colors: Math.min(Math.max(value, 2), 256) })
+            }
+          />
+        </SettingRow>
+        <SettingRow
+          title="Dithering"
+          helpText="How much to dither when reducing colours. 0 disables dithering."
+        >
+          <QualitySlider
+            value={png().dithering}
+            onChange={(value) => updatePng({ dithering: value })}
+            min={0}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Optimization Level"
+          helpText="How hard to try making the file smaller, from 0 (fastest) to 6 (slowest)."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={png().optimization_level.toString()}
+            onChange={(value) =>
+              updatePng({ optimization_level: Number.parseInt(value) })
+            }
+            options={["0", "1", "2", "3", "4", "5", "6"]}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Interlace"
+          helpText="Interlaced PNGs show a preview while loading, but are larger."
+        >
+          <SettingsToggle
+            value={png().interlace}
+            onChange={(value) => updatePng({ interlace: value })}
+          />
+        </SettingRow>
+      </SettingBox>
+      <div class="pt-8" />
+      <SettingBox title="WEBP">
+        <SettingRow
+          title="Mode"
+          helpText="Lossless and NearLossless work best for screenshots and UI assets. NearLossless uses WEBP Quality as its level."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={webp().mode}
+            onChange={(value) => updateWebp({ mode: value as WebpMode })}
+            options={webpModes}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Effort"
+          helpText="How hard to try making the file smaller, from 0 (fastest) to 6 (slowest)."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={webp().method.toString()}
+            onChange={(value) => updateWebp({ method: Number.parseInt(value) })}
+            options={["0", "1", "2", "3", "4", "5", "6"]}
+          />
+        </SettingRow>
+        <SettingRow title="Alpha Quality">
+          <QualitySlider
+            value={webp().alpha_quality}
+            onChange={(value) => updateWebp({ alpha_quality: value })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Sharp YUV"
+          helpText="Slower, but keeps colour edges sharper in lossy mode."
+        >
+          <SettingsToggle
+            value={webp().sharp_yuv}
+            onChange={(value) => updateWebp({ sharp_yuv: value })}
+          />
+        </SettingRow>
+      </SettingBox>
+      <div class="pt-8" />
+      <SettingBox title="TIFF">
+        <SettingRow
+          title="Compression"
+          helpText="All TIFF compression methods are lossless. Multi-page TIFFs keep their pages, or are split into one file per page when converted."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={tiff().compression}
+            onChange={(value) =>
+              updateTiff({ compression: value as TiffCompression })
+            }
+            options={tiffCompressions}
+          />
+        </SettingRow>
+        <SettingRow title="Deflate Level">
+          <SettingsSelect
+            class="w-32"
+            value={tiff().deflate_level}
+            onChange={(value) =>
+              updateTiff({ deflate_level: value as TiffDeflateLevel })
+            }
+            options={tiffDeflateLevels}
+          />
+        </SettingRow>
       </SettingBox>
       <div class="pt-8" />
       <SettingBox title="Resize">
@@ -168,6 +471,145 @@ function ProfilePage() {
         </SettingRow>
       </SettingBox>
       <div class="pt-8" />
+      <SettingBox title="Filters">
+        <SettingRow
+          title="Min File Size (KB)"
+          helpText="Smaller files are skipped. Setting this to 0 removes the limit."
+        >
+          <SettingsNumberInput
+            value={filters().min_size_kb}
+            onChange={(value) => updateFilters({ min_size_kb: Math.max(value, 0) })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Max File Size (KB)"
+          helpText="Larger files are skipped. Setting this to 0 removes the limit."
+        >
+          <SettingsNumberInput
+            value={filters().max_size_kb}
+            onChange={(value) => updateFilters({ max_size_kb: Math.max(value, 0) })}
+          />
+        </SettingRow>
+        <SettingRow title="Min Width">
+          <SettingsNumberInput
+            value={filters().min_width}
+            onChange={(value) => updateFilters({ min_width: Math.max(value, 0) })}
+          />
+        </SettingRow>
+        <SettingRow title="Max Width">
+          <SettingsNumberInput
+            value={filters().max_width}
+            onChange={(value) => updateFilters({ max_width: Math.max(value, 0) })}
+          />
+        </SettingRow>
+        <SettingRow title="Min Height">
+          <SettingsNumberInput
+            value={filters().min_height}
+            onChange={(value) => updateFilters({ min_height: Math.max(value, 0) })}
+          />
+        </SettingRow>
+        <SettingRow title="Max Height">
+          <SettingsNumberInput
+            value={filters().max_height}
+            onChange={(value) => updateFilters({ max_height: Math.max(value, 0) })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Modified After"
+          helpText="Files last changed before this day are skipped. Leave empty to include all."
+        >
+          <input
+            class="rounded-md border-0 bg-secondary py-1.5 shadow-sm sm:text-sm/6"
+            type="date"
+            value={
+              filters().modified_after > 0
+                ? new Date(filters().modified_after * 1000)
+                    .toISOString()
+                    .slice(0, 10)
+                : ""
+            }
+            onInput={(e) => {
+              const date = e.target.valueAsDate;
+              updateFilters({
+                modified_after: date ? Math.floor(date.getTime() / 1000) : 0,
+              });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Match Any"
+          helpText="Compress files that pass any of the size, dimension or date filters, instead of all of them."
+        >
+          <SettingsToggle
+            value={filters().match_any}
+            onChange={(value) => updateFilters({ match_any: value })}
+          />
+        </SettingRow>
+      </SettingBox>
+      <div class="pt-8" />
+      <SettingBox title="Folder Scanning">
+        <SettingRow
+          title="Include"
+          helpText="Comma separated globs, only matching images are added when set. Example: photos/**/*.jpg"
+        >
+          <SettingsInput
+            label="Include"
+            value={scan().include.join(",")}
+            onChange={(value) => updateScan({ include: value.split(",") })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Exclude"
+          helpText="Comma separated globs for files and folders to skip. Example: node_modules, **/build"
+        >
+          <SettingsInput
+            label="Exclude"
+            value={scan().exclude.join(",")}
+            onChange={(value) => updateScan({ exclude: value.split(",") })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Use Ignore Files"
+          helpText="Skip what .gitignore and .alicignore files in the folder list."
+        >
+          <SettingsToggle
+            value={scan().use_ignore_files}
+            onChange={(value) => updateScan({ use_ignore_files: value })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Max Depth"
+          helpText="Folder levels to look into, 1 is only the dropped folder. Setting this to 0 removes the limit."
+        >
+          <SettingsNumberInput
+            value={scan().max_depth}
+            onChange={(value) => updateScan({ max_depth: Math.max(value, 0) })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Skip Hidden Folders"
+          helpText="Skip folders whose name starts with a dot, like .git."
+        >
+          <SettingsToggle
+            value={scan().skip_hidden}
+            onChange={(value) => updateScan({ skip_hidden: value })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Symlinks"
+          helpText="Follow visits linked folders once, so links back up the tree can't loop."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={scan().symlinks}
+            onChange={(value) =>
+              updateScan({ symlinks: value as SymlinkPolicy })
+            }
+            options={symlinkPolicies}
+          />
+        </SettingRow>
+      </SettingBox>
+      <div class="pt-8" />
       <SettingBox title="Manage">
         <SettingRow title="Profile Name">
           <SettingsInput
@@ -220,12 +662,13 @@ function ProfilePage() {
 function QualitySlider(props: {
   value: number;
   onChange: (value: number) => void;
+  min?: number;
 }) {
   return (
     <div class="flex gap-4">
       <input
         type="range"
-        min="1"
+        min={props.min ?? 1}
         max="10"
         value={props.value / 10}
         onInput={(e) => {
@@ -8,6 +8,7 @@ import {
   createMemo,
   createSignal,
 } from "solid-js";
+import { commands } from "../bindings";
 import { ConfirmModal, confirmModal } from "./ConfirmModal";
 import {
   SettingBox,
@@ -16,11 +17,20 @@ import {
   SettingsInput,
   SettingsNumberInput,
   SettingsPage,
+  SettingsToggle,
 } from "./SettingsUI";
 import {
   createProfile,
+  getLimits,
   resetSettings,
+  setBackupOriginals,
+  setIncludeExtensionless,
+  setLimits,
+  setMeasureQuality,
+  setMemoryBudget,
+  setRecompressProcessed,
   setThreads,
+  setTimeLimit,
   settings,
 } from "./settingsData";
 
@@ -98,6 +108,140 @@ function GeneralPage() {
             }}
           />
         </SettingRow>
+        <SettingRow
+          title="Memory Budget (MB)"
+          helpText="Memory parallel images may use once decoded. Large images run with fewer threads. Setting this to 0 will use 2048 MB."
+        >
+          <SettingsNumberInput
+            value={settings.memory_budget || 0}
+            onChange={(value) => {
+              setMemoryBudget(value);
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Time Limit (seconds)"
+          helpText="Give up on an image that takes longer than this, so the rest of the batch can continue. Setting this to 0 removes the limit."
+        >
+          <SettingsNumberInput
+            value={settings.time_limit || 0}
+            onChange={(value) => {
+              setTimeLimit(value);
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Include Files Without Extension"
+          helpText="When adding a folder, also check files without an extension for image data."
+        >
+          <SettingsToggle
+            value={settings.include_extensionless ?? false}
+            onChange={(value) => {
+              setIncludeExtensionless(value);
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Recompress Processed Files"
+          helpText="Compress images again even if they are Alic outputs, or were already compressed with the same profile settings."
+        >
+          <SettingsToggle
+            value={settings.recompress_processed ?? false}
+            onChange={(value) => {
+              setRecompressProcessed(value);
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Clear History"
+          helpText="Forget which images have been compressed, so they can be compressed again."
+        >
+          <SettingsButton
+            onClick={async () => {
+              confirmModal({
+                text: "Are you sure you want to clear the compression history?",
+                onConfirm: () => commands.clearHistory(),
+              });
+            }}
+            style="danger"
+          >
+            Clear
+          </SettingsButton>
+        </SettingRow>
+        <SettingRow
+          title="Measure Quality"
+          helpText="Compare every compressed image to its original and report PSNR, SSIM and the largest pixel error. Makes compression slower."
+        >
+          <SettingsToggle
+            value={settings.measure_quality ?? false}
+            onChange={(value) => {
+              setMeasureQuality(value);
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Back Up Originals"
+          helpText="Keep a copy of every image that gets overwritten, so a batch can be undone even if the Trash is emptied."
+        >
+          <SettingsToggle
+            value={settings.backup_originals ?? false}
+            onChange={(value) => {
+              setBackupOriginals(value);
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Delete Old Backups"
+          helpText="Delete backups and undo information older than 30 days."
+        >
+          <SettingsButton
+            onClick={async () => {
+              confirmModal({
+                text: "Are you sure you want to delete backups older than 30 days? Those images can no longer be restored.",
+                onConfirm: () => commands.pruneBackups(30),
+              });
+            }}
+            style="danger"
+          >
+            Delete
+          </SettingsButton>
+        </SettingRow>
+        <SettingRow
+          title="Max Image Width"
+          helpText="Images wider than this are refused before decoding. Setting this to 0 removes the limit."
+        >
+          <SettingsNumberInput
+            value={getLimits().max_width}
+            onChange={(value) => setLimits({ max_width: value })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Max Image Height"
+          helpText="Images taller than this are refused before decoding. Setting this to 0 removes the limit."
+        >
+          <SettingsNumberInput
+            value={getLimits().max_height}
+            onChange={(value) => setLimits({ max_height: value })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Max Decode Memory (MB)"
+          helpText="Largest amount of memory a single image may need once decoded. Setting this to 0 removes the limit."
+        >
+          <SettingsNumberInput
+            value={getLimits().max_alloc_mb}
+            onChange={(value) => setLimits({ max_alloc_mb: value })}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Max File Size (MB)"
+          helpText="Larger files are skipped. Setting this to 0 removes the limit."
+        >
+          <SettingsNumberInput
+            value={getLimits().max_file_mb}
+            onChange={(value) => setLimits({ max_file_mb: value })}
+          />
+        </SettingRow>
         <SettingRow title="Reset All Settings">
           <SettingsButton
             onClick={async () => {
@@ -1,6 +1,11 @@
 import { settingsChangedListener } from "@/listeners";
 import { createStore } from "solid-js/store";
-import { type ProfileData, type SettingsData, commands } from "../bindings";
+import {
+  type DecodeLimits,
+  type ProfileData,
+  type SettingsData,
+  commands,
+} from "../bindings";
 
 const [settings, setSettings] = createStore<SettingsData>(await getSettings());
 
@@ -24,7 +29,7 @@ function debounce<F extends (...args: Parameters<F>) => ReturnType<F>>(
 async function getSettings() {
   const opt = await commands.getSettings();
   if (opt.status === "error") {
-    throw new Error(opt.error);
+    throw new Error(opt.error.error);
   }
   // console.log(opt.data);
   return opt.data;
@@ -49,6 +54,62 @@ function setThreads(threads: SettingsData["threads"]) {
   saveSettings();
 }
 
+function setMemoryBudget(memoryBudget: SettingsData["memory_budget"]) {
+  setSettings("memory_budget", Math.max(memoryBudget || 0, 0));
+  saveSettings();
+}
+
+function setTimeLimit(timeLimit: SettingsData["time_limit"]) {
+  setSettings("time_limit", Math.max(timeLimit || 0, 0));
+  saveSettings();
+}
+
+function setIncludeExtensionless(
+  includeExtensionless: SettingsData["include_extensionless"],
+) {
+  setSettings("include_extensionless", includeExtensionless ?? false);
+  saveSettings();
+}
+
+function setRecompressProcessed(
+  recompressProcessed: SettingsData["recompress_processed"],
+) {
+  setSettings("recompress_processed", recompressProcessed ?? false);
+  saveSettings();
+}
+
+function setBackupOriginals(
+  backupOriginals: SettingsData["backup_originals"],
+) {
+  setSettings("backup_originals", backupOriginals ?? false);
+  saveSettings();
+}
+
+function setMeasureQuality(measureQuality: SettingsData["measure_quality"]) {
+  setSettings("measure_quality", measureQuality ?? false);
+  saveSettings();
+}
+
+const defaultDecodeLimits: DecodeLimits = {
+  max_width: 20000,
+  max_height: 20000,
+  max_alloc_mb: 2048,
+  max_file_mb: 512,
+};
+
+function getLimits(): DecodeLimits {
+  return settings.limits ?? defaultDecodeLimits;
+}
+
+function setLimits(update: Partial<DecodeLimits>) {
+  const limits = { ...getLimits(), ...update };
+  for (const key of Object.keys(limits) as (keyof DecodeLimits)[]) {
+    limits[key] = Math.max(limits[key] || 0, 0);
+  }
+  setSettings("limits", limits);
+  saveSettings();
+}
+
 function updateProfile(profileid: number, update: Partial<ProfileData>) {
   const profileIdx = settings.profiles.findIndex((p) => p.id === profileid);
   if (profileIdx === -1) {
@@ -96,6 +157,14 @@ function getProfileActive() {
 export {
   settings,
   setThreads,
+  setMemoryBudget,
+  setTimeLimit,
+  setIncludeExtensionless,
+  setRecompressProcessed,
+  setBackupOriginals,
+  setMeasureQuality,
+  getLimits,
+  setLimits,
   resetSettings,
   updateProfile,
   deleteProfile,
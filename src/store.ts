@@ -1,13 +1,27 @@
 import { createStore } from "solid-js/store";
-import { type FileEntry, commands } from "./bindings";
+import {
+  type FileEntry,
+  type ReportFormat,
+  type ScanError,
+  type ScanProgress,
+  commands,
+} from "./bindings";
 import { compressImage } from "./compress";
-import { clearFilesListener } from "./listeners";
+import {
+  clearFilesListener,
+  scanErrorListener,
+  scanProgressListener,
+} from "./listeners";
 import { getProfileActive, settings } from "./settings/settingsData";
 import { Semaphore } from "./utils";
 
 clearFilesListener(clearFiles);
+scanProgressListener(updateScan);
+scanErrorListener(addScanError);
 
 const CPU_COUNT = await commands.getCpuCount();
+const DEFAULT_MEMORY_BUDGET_MB = 2048;
+const QUEUE_SAVE_INTERVAL_MS = 1000;
 const semaphore = new Semaphore(0);
 syncSemaphore();
 
@@ -15,14 +29,77 @@ type ReadonlyFileEntry = Readonly<FileEntry>;
 
 interface Store {
   files: ReadonlyFileEntry[];
+  // Running folder scans, by the path that was dropped
+  scans: Record<string, ScanProgress | undefined>;
+  scanErrors: ScanError[];
+  // Files left from the last run, until they're resumed or discarded
+  resumable: FileEntry[];
 }
 
 const [store, setStore] = createStore<Store>({
   files: [],
+  scans: {},
+  scanErrors: [],
+  resumable: await loadQueue(),
 });
 
+// Changes are saved to the queue at most once a second, a crash only loses the last few
+const unsavedFiles = new Map<string, FileEntry>();
+setInterval(saveQueue, QUEUE_SAVE_INTERVAL_MS);
+
+async function loadQueue(): Promise<FileEntry[]> {
+  const result = await commands.getQueue();
+  if (result.status === "error") {
+    console.log(result.error);
+    return [];
+  }
+  if (result.data.every(isFinished)) {
+    commands.clearQueue();
+    return [];
+  }
+  return result.data;
+}
+
+async function saveQueue() {
+  if (unsavedFiles.size === 0) {
+    return;
+  }
+  const files = [...unsavedFiles.values()];
+  unsavedFiles.clear();
+  const result = await commands.queueFiles(files);
+  if (result.status === "error") {
+    console.log(result.error);
+    // Tried again on the next save, unless changed or removed since
+    for (const file of files) {
+      const current = store.files.some((f) => f.path === file.path);
+      if (current && !unsavedFiles.has(file.path)) {
+        unsavedFiles.set(file.path, file);
+      }
+    }
+  }
+}
+
+function isFinished(file: FileEntry) {
+  return file.status !== "Processing" && file.status !== "Compressing";
+}
+
+// Finished files come back as they were, the rest are compressed again
+function resumeQueue() {
+  const files = store.resumable;
+  setStore("resumable", []);
+  const finished = files.filter(isFinished);
+  setStore("files", (f) => [...f, ...finished]);
+  addFiles(files.filter((f) => !isFinished(f)).map((f) => f.path));
+}
+
+function discardQueue() {
+  setStore("resumable", []);
+  commands.clearQueue();
+}
+
 function syncSemaphore() {
   semaphore.maxConcurrent = settings.threads || CPU_COUNT;
+  semaphore.maxWeight = settings.memory_budget || DEFAULT_MEMORY_BUDGET_MB;
 }
 
 function newFileEntry(
@@ -38,21 +115,44 @@ function newFileEntry(
     ext: data.ext ?? "",
     error: data.error ?? null,
     savings: data.savings ?? null,
+    outPath: data.outPath ?? null,
+    errorType: data.errorType ?? null,
+    width: data.width ?? null,
+    height: data.height ?? null,
+    imageType: data.imageType ?? null,
+    outType: data.outType ?? null,
   };
 }
 
-async function addFile(path: string) {
-  if (store.files.find((f) => f.path === path)) {
+function addFile(path: string) {
+  addFiles([path]);
+}
+
+// Scans send thousands of paths at once, so they go into the store in one update
+function addFiles(paths: string[]) {
+  const known = new Set(store.files.map((f) => f.path));
+  const files = [...new Set(paths)]
+    .filter((path) => !known.has(path))
+    .map((path) => newFileEntry(path, {}));
+  if (files.length === 0) {
     return;
   }
+  setStore("files", (f) => [...f, ...files]);
+  for (const file of files) {
+    unsavedFiles.set(file.path, file);
+  }
+  for (const file of files) {
+    processFile(file);
+  }
+}
 
-  let file = newFileEntry(path, {});
-  setStore("files", (f) => [...f, file]);
-
-  const fileResult = await commands.getFileInfo(path);
+async function processFile(_file: FileEntry) {
+  let file = _file;
+  const fileResult = await commands.getFileInfo(file.path);
   if (fileResult.status === "error") {
     console.log(fileResult.error);
-    updateFile(file, { error: fileResult.error, status: "Error" });
+    const { error, errorType } = fileResult.error;
+    updateFile(file, { error, errorType, status: "Error" });
     return;
   }
 
@@ -60,14 +160,19 @@ async function addFile(path: string) {
     file: fileResult.data.filename,
     ext: fileResult.data.extension,
     originalSize: fileResult.data.size,
+    width: fileResult.data.width || null,
+    height: fileResult.data.height || null,
+    imageType: fileResult.data.image_type,
   };
   file = updateFile(file, update);
-  await semaphore.acquire();
+  // Big images take more of the memory budget, so fewer run at once
+  const weight = fileResult.data.peak_mb;
+  await semaphore.acquire(weight);
   syncSemaphore();
   try {
     await compressFile(file);
   } finally {
-    semaphore.release();
+    semaphore.release(weight);
   }
 }
 
@@ -75,25 +180,34 @@ async function compressFile(_file: FileEntry) {
   let file = updateFile(_file, { status: "Compressing" });
   const compressResult = await compressImage(getProfileActive(), file);
   if (compressResult.status === "error") {
-    if (compressResult.error.errorType === "NotSmaller") {
-      updateFile(file, {
-        error: compressResult.error.error,
-        status: "AlreadySmaller",
-      });
+    const { error, errorType } = compressResult.error;
+    if (errorType === "NotSmaller") {
+      updateFile(file, { error, errorType, status: "AlreadySmaller" });
+      return;
+    }
+    if (errorType === "Skipped" || errorType === "AlreadyProcessed") {
+      updateFile(file, { error, errorType, status: "Skipped" });
       return;
     }
-    updateFile(file, { error: compressResult.error.error, status: "Error" });
+    updateFile(file, { error, errorType, status: "Error" });
+    // The timed out encode keeps its memory until its thread stops
+    if (errorType === "Timeout") {
+      await commands.waitForJob(file.path);
+    }
     return;
   }
 
-  const outSize = compressResult.data.outSize;
+  const { outSize, bytesSaved } = compressResult.data;
   let savings = null;
-  if (file.originalSize !== null) {
-    savings = ((file.originalSize - outSize) / file.originalSize) * 100;
+  if (file.originalSize) {
+    savings = (bytesSaved / file.originalSize) * 100;
   }
   file = updateFile(file, {
     status: "Complete",
     size: outSize,
+    outPath: compressResult.data.outPath,
+    imageType: compressResult.data.inType,
+    outType: compressResult.data.outType,
     savings,
   });
 }
@@ -104,16 +218,67 @@ function updateFile(
 ): ReadonlyFileEntry {
   const newFile: ReadonlyFileEntry = { ...file, ...update };
   setStore("files", (f) => f.path === file.path, newFile);
+  unsavedFiles.set(newFile.path, newFile);
   return newFile;
 }
 
+function updateScan(progress: ScanProgress) {
+  if (progress.done) {
+    setStore("scans", progress.root, undefined);
+    return;
+  }
+  setStore("scans", progress.root, progress);
+}
+
+function addScanError(error: ScanError) {
+  console.log(`Cannot scan ${error.path}: ${error.error}`);
+  setStore("scanErrors", (e) => [...e, error]);
+}
+
 function clearFiles() {
+  commands.cancelScan();
+  commands.newBatch();
   semaphore.cancel();
-  setStore("files", []);
+  unsavedFiles.clear();
+  commands.clearQueue();
+  setStore({ files: [], scanErrors: [], resumable: [] });
+}
+
+// Puts back everything compressed since the list was last cleared
+async function undoFiles() {
+  const result = await commands.undoBatch(null);
+  if (result.status === "error") {
+    console.log(result.error);
+    return;
+  }
+  clearFiles();
 }
 
-function removeFile(file: FileEntry) {
+async function exportReport(format: ReportFormat, path: string) {
+  const result = await commands.exportReport([...store.files], format, path);
+  if (result.status === "error") {
+    console.log(result.error);
+  }
+}
+
+async function removeFile(file: FileEntry) {
   setStore("files", (f) => f.filter((f) => f.path !== file.path));
+  unsavedFiles.delete(file.path);
+  const result = await commands.unqueueFiles([file.path]);
+  if (result.status === "error") {
+    console.log(result.error);
+  }
 }
 
-export { store, addFile, updateFile, clearFiles, removeFile };
+export {
+  store,
+  addFile,
+  addFiles,
+  updateFile,
+  clearFiles,
+  undoFiles,
+  exportReport,
+  removeFile,
+  resumeQueue,
+  discardQueue,
+};
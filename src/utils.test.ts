@@ -0,0 +1,71 @@
+import { describe, expect, test } from "bun:test";
+import { Semaphore } from "./utils";
+
+// Acquires in order and records when each one got through
+function start(semaphore: Semaphore, weights: number[]) {
+  const started: number[] = [];
+  for (const [i, weight] of weights.entries()) {
+    semaphore.acquire(weight).then(() => started.push(i));
+  }
+  return started;
+}
+
+// Lets resolved acquires run their callbacks
+const settle = () => new Promise((resolve) => setTimeout(resolve, 0));
+
+describe("Semaphore", () => {
+  test("limits the number of running jobs", async () => {
+    const semaphore = new Semaphore(2);
+    const started = start(semaphore, [0, 0, 0]);
+    await settle();
+    expect(started).toEqual([0, 1]);
+    semaphore.release();
+    await settle();
+    expect(started).toEqual([0, 1, 2]);
+  });
+
+  test("limits the total weight", async () => {
+    const semaphore = new Semaphore(4);
+    semaphore.maxWeight = 100;
+    const started = start(semaphore, [60, 60]);
+    await settle();
+    expect(started).toEqual([0]);
+    semaphore.release(60);
+    await settle();
+    expect(started).toEqual([0, 1]);
+  });
+
+  test("runs a job heavier than the budget on its own", async () => {
+    const semaphore = new Semaphore(4);
+    semaphore.maxWeight = 100;
+    const started = start(semaphore, [10, 500, 10]);
+    await settle();
+    expect(started).toEqual([0]);
+    semaphore.release(10);
+    await settle();
+    expect(started).toEqual([0, 1]);
+    semaphore.release(500);
+    await settle();
+    expect(started).toEqual([0, 1, 2]);
+  });
+
+  test("doesn't let light jobs pass a waiting heavy one", async () => {
+    const semaphore = new Semaphore(4);
+    semaphore.maxWeight = 100;
+    const started = start(semaphore, [50, 80, 10, 10]);
+    await settle();
+    expect(started).toEqual([0]);
+    semaphore.release(50);
+    await settle();
+    expect(started).toEqual([0, 1, 2, 3]);
+  });
+
+  test("drops waiting jobs when cancelled", async () => {
+    const semaphore = new Semaphore(1);
+    const started = start(semaphore, [0, 0]);
+    semaphore.cancel();
+    semaphore.release();
+    await settle();
+    expect(started).toEqual([0]);
+  });
+});
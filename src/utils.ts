@@ -9,40 +9,57 @@ function toHumanReadableSize(size?: number | null) {
   return `${(size / 1024 ** i).toFixed(1)} ${["B", "kB", "MB", "GB"][i]}`;
 }
 
+// Limits the number of running jobs, and optionally their total weight.
+// A job heavier than maxWeight still runs, but only on its own.
+// Jobs start in the order they asked, so a heavy one is never passed over for good.
 class Semaphore {
   private running = 0;
-  private queue: (() => void)[] = [];
+  private weight = 0;
+  private queue: { weight: number; resolve: () => void }[] = [];
+  public maxWeight = Number.POSITIVE_INFINITY;
 
   constructor(public maxConcurrent: number) {}
 
-  async acquire(): Promise<void> {
-    if (this.running >= this.maxConcurrent) {
+  async acquire(weight = 0): Promise<void> {
+    if (this.queue.length > 0 || !this.fits(weight)) {
       return new Promise<void>((resolve) => {
-        this.queue.push(resolve);
+        this.queue.push({ weight, resolve });
       });
     }
-    this.running++;
+    this.take(weight);
   }
 
   cancel(): void {
     this.queue = [];
   }
 
-  release(): void {
+  release(weight = 0): void {
     this.running--;
-    if (this.running < 0) {
+    this.weight -= weight;
+    if (this.running <= 0) {
       this.running = 0;
+      this.weight = 0;
     }
-    while (this.running < this.maxConcurrent) {
-      const next = this.queue.shift();
-      if (next) {
-        this.running++;
-        next();
-      } else {
-        break;
-      }
+    // The first waiter holds back everyone behind it until it fits
+    while (this.queue.length > 0 && this.fits(this.queue[0].weight)) {
+      const next = this.queue[0];
+      this.queue.shift();
+      this.take(next.weight);
+      next.resolve();
     }
   }
+
+  private fits(weight: number): boolean {
+    if (this.running >= this.maxConcurrent) {
+      return false;
+    }
+    return this.running === 0 || this.weight + weight <= this.maxWeight;
+  }
+
+  private take(weight: number): void {
+    this.running++;
+    this.weight += weight;
+  }
 }
 
 export { toHumanReadableSize, Semaphore };